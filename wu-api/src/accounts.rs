@@ -0,0 +1,364 @@
+//! User accounts
+
+use crate::auth::{Grant, Permission};
+use crate::data::{parse, read_file, write_file};
+use crate::history::now_secs;
+use jzon::JsonValue;
+use std::collections::HashMap;
+use std::fs::{read, rename};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use wu::crypto::{Argon2Params, argon2_hash_with, argon2_verify, ct_eq, hash, random};
+use wu::{Fail, Result};
+
+/// Version of the accounts file format
+const VERSION: u64 = 1;
+
+/// User account
+#[derive(Clone, Debug, Default)]
+pub struct Account {
+    /// Argon2id hash of the client-side password hash
+    pub password: String,
+
+    /// Granted roles and permissions
+    pub grants: Vec<Grant>,
+
+    /// E-mail address
+    pub email: Option<String>,
+
+    /// Unix time in seconds the account was created, none if imported
+    pub created: Option<u64>,
+
+    /// Unix time in seconds of the last successful login
+    pub last_login: Option<u64>,
+
+    /// Login is refused and permissions are revoked
+    pub disabled: bool,
+
+    /// Only the own password may be changed until it is
+    pub must_change_password: bool,
+}
+
+impl Account {
+    /// Create account now
+    pub fn new(password: String, grants: Vec<Grant>) -> Self {
+        Self {
+            password,
+            grants,
+            created: Some(now_secs()),
+            ..Self::default()
+        }
+    }
+
+    /// Parse account from JSON
+    fn parse(json: &JsonValue) -> Result<Self> {
+        let grants = json["grants"]
+            .members()
+            .map(|grant| Grant::parse(grant.as_str().unwrap_or_default()))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            password: json["password"]
+                .as_str()
+                .ok_or_else(|| Fail::new("account without password"))?
+                .to_string(),
+            grants,
+            email: json["email"].as_str().map(str::to_string),
+            created: json["created"].as_u64(),
+            last_login: json["lastlogin"].as_u64(),
+            disabled: json["disabled"].as_bool().unwrap_or(false),
+            must_change_password: json["mustchange"].as_bool().unwrap_or(false),
+        })
+    }
+
+    /// Check if account is enabled and grants permission on server
+    fn permits(&self, permission: Permission, server: Option<&str>) -> bool {
+        !self.disabled
+            && self
+                .grants
+                .iter()
+                .any(|grant| grant.permits(permission, server))
+    }
+
+    /// Grants as strings
+    pub fn grants_json(&self) -> JsonValue {
+        let grants = self.grants.iter().map(Grant::to_string);
+        grants.collect::<Vec<_>>().into()
+    }
+
+    /// Serialize account without password hash
+    pub fn to_json(&self) -> JsonValue {
+        object!(
+            grants: self.grants_json(),
+            email: self.email.as_deref(),
+            created: self.created,
+            lastlogin: self.last_login,
+            disabled: self.disabled,
+            mustchange: self.must_change_password
+        )
+    }
+
+    /// Serialize account for the accounts file
+    fn store(&self) -> JsonValue {
+        let mut json = self.to_json();
+        json["password"] = self.password.as_str().into();
+        json
+    }
+}
+
+/// User accounts stored as versioned JSON
+#[derive(Debug)]
+pub struct Accounts {
+    file: PathBuf,
+    accounts: HashMap<String, Account>,
+    params: Argon2Params,
+    unsaved: AtomicBool,
+}
+
+impl Accounts {
+    /// Open accounts file in data directory, importing users.wdb if it does not exist
+    pub fn open(data_dir: impl AsRef<Path>, params: Argon2Params) -> Result<Self> {
+        // read accounts file
+        let data_dir = data_dir.as_ref();
+        let file = data_dir.join("users.json");
+        let buf = read_file(&file)?;
+        if buf.is_empty() {
+            return Self::migrate(file, data_dir, params);
+        }
+        let buf = String::from_utf8(buf).or_else(Fail::from)?;
+
+        // check version and parse accounts
+        let json = jzon::parse(&buf).or_else(Fail::from)?;
+        match json["version"].as_u64() {
+            Some(VERSION) => {}
+            version => return Fail::from(format!("unsupported accounts version {version:?}")),
+        }
+        let mut accounts = HashMap::new();
+        for (name, account) in json["users"].entries() {
+            accounts.insert(name.to_string(), Account::parse(account)?);
+        }
+        Ok(Self {
+            file,
+            accounts,
+            params,
+            unsaved: AtomicBool::new(false),
+        })
+    }
+
+    /// Import username=hash lines of the previous format as administrators
+    fn migrate(file: PathBuf, data_dir: &Path, params: Argon2Params) -> Result<Self> {
+        // read legacy users
+        let users_file = data_dir.join("users.wdb");
+        let users = match read(&users_file) {
+            Ok(buf) => parse(buf)?,
+            Err(_) => HashMap::new(),
+        };
+
+        // create accounts, every legacy user was an administrator
+        let mut accounts = HashMap::new();
+        for (name, password) in users {
+            let account = Account {
+                password,
+                grants: vec![Grant::parse("admin")?],
+                ..Account::default()
+            };
+            accounts.insert(name, account);
+        }
+
+        // write new format and keep legacy file aside
+        let migrated = Self {
+            file,
+            accounts,
+            params,
+            unsaved: AtomicBool::new(false),
+        };
+        migrated.save()?;
+        if users_file.exists() {
+            let mut aside = users_file.clone().into_os_string();
+            aside.push(".migrated");
+            rename(&users_file, aside).or_else(Fail::from)?;
+        }
+        Ok(migrated)
+    }
+
+    /// Check if no account exists
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// Get account
+    pub fn get(&self, name: &str) -> Option<&Account> {
+        self.accounts.get(name)
+    }
+
+    /// All accounts
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Account)> {
+        self.accounts.iter()
+    }
+
+    /// Grants of user
+    pub fn grants(&self, name: &str) -> &[Grant] {
+        self.accounts
+            .get(name)
+            .map_or(&[], |account| &account.grants)
+    }
+
+    /// Check if user may do action on server, none for actions not bound to a server
+    pub fn allowed(&self, name: &str, permission: Permission, server: Option<&str>) -> bool {
+        self.accounts.get(name).is_some_and(|account| {
+            !account.must_change_password && account.permits(permission, server)
+        })
+    }
+
+    /// Check if user may do action on any server
+    pub fn allowed_any(&self, name: &str, permission: Permission) -> bool {
+        self.accounts.get(name).is_some_and(|account| {
+            !account.must_change_password
+                && !account.disabled
+                && account
+                    .grants
+                    .iter()
+                    .any(|grant| grant.permissions().contains(&permission))
+        })
+    }
+
+    /// Check if user holds everything contained in grants
+    pub fn grantable(&self, name: &str, grants: &[Grant]) -> Result<()> {
+        for grant in grants {
+            let server = grant.server();
+            if !grant
+                .permissions()
+                .iter()
+                .all(|&permission| self.allowed(name, permission, server))
+            {
+                return Fail::from(format!("not allowed to grant {grant}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Password hashing parameters
+    pub fn params(&self) -> Argon2Params {
+        self.params
+    }
+
+    /// Stored password hash of enabled account
+    pub fn password(&self, name: &str) -> Option<&str> {
+        self.accounts
+            .get(name)
+            .filter(|account| !account.disabled)
+            .map(|account| account.password.as_str())
+    }
+
+    /// Remember login with verified password hash, replacing it if rehashed, false if changed meanwhile
+    pub fn login(&mut self, name: &str, verified: &str, rehashed: Option<String>) -> Result<bool> {
+        let Some(account) = self
+            .accounts
+            .get_mut(name)
+            .filter(|account| !account.disabled && account.password == verified)
+        else {
+            return Ok(false);
+        };
+        account.last_login = Some(now_secs());
+
+        // save rehashed password now, login time with the next save
+        match rehashed {
+            Some(password) => {
+                account.password = password;
+                self.save()?;
+            }
+            None => self.unsaved.store(true, Ordering::Relaxed),
+        }
+        Ok(true)
+    }
+
+    /// Write accounts to file if login times changed since the last save
+    pub fn save_unsaved(&self) -> Result<()> {
+        match self.unsaved.load(Ordering::Relaxed) {
+            true => self.save(),
+            false => Ok(()),
+        }
+    }
+
+    /// Add new account
+    pub fn create(&mut self, name: &str, account: Account) -> Result<()> {
+        if self.accounts.contains_key(name) {
+            return Fail::from("username already exists");
+        }
+        self.accounts.insert(name.to_string(), account);
+        self.save()
+    }
+
+    /// Change account, keeping at least one user able to manage users
+    pub fn update(&mut self, name: &str, change: impl FnOnce(&mut Account)) -> Result<()> {
+        let account = self
+            .accounts
+            .get_mut(name)
+            .ok_or_else(|| Fail::new("user does not exist"))?;
+        let prev = account.clone();
+        change(account);
+        self.keep_manager(name, Some(prev))
+    }
+
+    /// Rename account
+    pub fn rename(&mut self, name: &str, new_name: &str) -> Result<()> {
+        if self.accounts.contains_key(new_name) {
+            return Fail::from("new username already exists");
+        }
+        let account = self
+            .accounts
+            .remove(name)
+            .ok_or_else(|| Fail::new("user does not exist"))?;
+        self.accounts.insert(new_name.to_string(), account);
+        self.save()
+    }
+
+    /// Delete account, keeping at least one user able to manage users
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        let prev = self.accounts.remove(name);
+        self.keep_manager(name, prev)
+    }
+
+    /// Restore previous account if no user can manage users anymore, otherwise save
+    fn keep_manager(&mut self, name: &str, prev: Option<Account>) -> Result<()> {
+        let managed = self
+            .accounts
+            .values()
+            .any(|account| account.permits(Permission::ManageUsers, None));
+        if managed {
+            return self.save();
+        }
+        match prev {
+            Some(prev) => self.accounts.insert(name.to_string(), prev),
+            None => self.accounts.remove(name),
+        };
+        Fail::from("at least one user must be able to manage users")
+    }
+
+    /// Write accounts to file
+    pub fn save(&self) -> Result<()> {
+        // serialize
+        let mut users = JsonValue::new_object();
+        for (name, account) in &self.accounts {
+            users[name.as_str()] = account.store();
+        }
+        let json = object!(version: VERSION, users: users);
+
+        // write to temporary file and replace
+        write_file(&self.file, json.pretty(4).as_bytes())?;
+        self.unsaved.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Hash client-side password hash for storage
+pub fn password_hash(client_hash: &str, params: &Argon2Params) -> Result<String> {
+    if client_hash.len() != 64 || !client_hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Fail::from("password is not a password hash");
+    }
+    argon2_hash_with(client_hash, random(16), params)
+}
+
+/// Verify client-side password hash against Argon2 hash or SHA3 hash stored by earlier versions of /user/update
+pub fn password_verify(stored: &str, client_hash: &str) -> bool {
+    argon2_verify(stored, client_hash) || ct_eq(stored.as_bytes(), hash(client_hash).as_bytes())
+}
@@ -0,0 +1,552 @@
+//! Alert rules
+
+use crate::common::*;
+use crate::data::{read_file, write_file};
+use jzon::JsonValue;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use wu::{Fail, Result};
+
+/// Interval in seconds in which rules are evaluated
+pub const EVALUATE_INTERVAL: u64 = 10;
+
+/// Number of resolved alerts kept
+const RESOLVED_KEEP: usize = 100;
+
+/// Evaluations without samples after which a firing alert of a host resolves
+const STALE_EVALUATIONS: u64 = 3;
+
+/// Rules written to the rules file if it does not exist
+const DEFAULT_RULES: &str = r#"[
+    { "name": "disk-full", "metric": "disk", "op": ">", "threshold": 90, "clear": 85, "for": 300 },
+    { "name": "memory-high", "metric": "mem", "op": ">", "threshold": 95, "clear": 90, "for": 300 },
+    { "name": "cpu-pinned", "metric": "cpu", "op": ">", "threshold": 95, "clear": 80, "for": 300 },
+    { "name": "server-offline", "metric": "offline", "for": 60 }
+]
+"#;
+
+/// Value a rule is evaluated on
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Metric {
+    /// Host CPU usage in percent
+    Cpu,
+
+    /// Host memory usage in percent
+    Mem,
+
+    /// Root filesystem of host in percent
+    Disk,
+
+    /// Host swap usage in percent
+    Swap,
+
+    /// Server client disconnected, 1 if offline
+    Offline,
+}
+
+impl Metric {
+    /// Parse metric name
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "cpu" => Self::Cpu,
+            "mem" => Self::Mem,
+            "disk" => Self::Disk,
+            "swap" => Self::Swap,
+            "offline" => Self::Offline,
+            _ => return None,
+        })
+    }
+
+    /// Metric name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Cpu => "cpu",
+            Self::Mem => "mem",
+            Self::Disk => "disk",
+            Self::Swap => "swap",
+            Self::Offline => "offline",
+        }
+    }
+}
+
+/// Threshold rule with hysteresis
+#[derive(Clone, Debug)]
+pub struct Rule {
+    /// Unique rule name
+    pub name: String,
+
+    /// Evaluated metric
+    pub metric: Metric,
+
+    /// Trigger above threshold, otherwise below
+    pub above: bool,
+
+    /// Value at which the rule triggers
+    pub threshold: f64,
+
+    /// Value the metric has to return to before the alert resolves
+    pub clear: f64,
+
+    /// Seconds the rule has to trigger before the alert fires
+    pub duration: u64,
+}
+
+impl Rule {
+    /// Parse rule from JSON
+    fn parse(json: &JsonValue) -> Result<Self> {
+        // name and metric
+        let name = json["name"]
+            .as_str()
+            .ok_or_else(|| Fail::new("rule name required"))?;
+        let metric = json["metric"]
+            .as_str()
+            .and_then(Metric::parse)
+            .ok_or_else(|| Fail::new(format!("rule {name} has no valid metric")))?;
+
+        // thresholds, offline only triggers on state
+        let above = match json["op"].as_str().unwrap_or(">") {
+            ">" => true,
+            "<" => false,
+            op => return Fail::from(format!("rule {name} has invalid op {op}")),
+        };
+        let threshold = match metric {
+            Metric::Offline => 0.5,
+            _ => json["threshold"]
+                .as_f64()
+                .ok_or_else(|| Fail::new(format!("rule {name} requires threshold")))?,
+        };
+        let clear = match metric {
+            Metric::Offline => threshold,
+            _ => json["clear"].as_f64().unwrap_or(threshold),
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            metric,
+            above: above || metric == Metric::Offline,
+            threshold,
+            clear,
+            duration: json["for"].as_u64().unwrap_or(0),
+        })
+    }
+
+    /// Check if value crosses threshold
+    fn triggered(&self, value: f64) -> bool {
+        match self.above {
+            true => value > self.threshold,
+            false => value < self.threshold,
+        }
+    }
+
+    /// Check if value returned to clear level
+    fn cleared(&self, value: f64) -> bool {
+        match self.above {
+            true => value <= self.clear,
+            false => value >= self.clear,
+        }
+    }
+
+    /// Serialize rule to JSON
+    pub fn to_json(&self) -> JsonValue {
+        object!(
+            name: self.name.as_str(),
+            metric: self.metric.as_str(),
+            op: if self.above { ">" } else { "<" },
+            threshold: self.threshold,
+            clear: self.clear,
+            for: self.duration
+        )
+    }
+}
+
+/// Alert state
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum State {
+    /// Rule triggers but not long enough
+    Pending,
+
+    /// Rule triggered long enough
+    Firing,
+
+    /// Metric returned to clear level
+    Resolved,
+}
+
+impl State {
+    /// State name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Firing => "firing",
+            Self::Resolved => "resolved",
+        }
+    }
+}
+
+/// Alert of a rule for a host or server
+#[derive(Clone, Debug)]
+pub struct Alert {
+    /// Unique alert id
+    pub id: u64,
+
+    /// Rule name
+    pub rule: String,
+
+    /// Metric of the rule
+    pub metric: Metric,
+
+    /// Host or server name
+    pub target: String,
+
+    /// Current state
+    pub state: State,
+
+    /// Latest value of the metric
+    pub value: f64,
+
+    /// Unix time in seconds the rule started triggering
+    pub since: u64,
+
+    /// Unix time in seconds of the latest sample
+    pub seen: u64,
+
+    /// Unix time in seconds the alert fired
+    pub fired: Option<u64>,
+
+    /// Unix time in seconds the alert resolved
+    pub resolved: Option<u64>,
+
+    /// User and unix time in seconds the alert was acknowledged
+    pub acked: Option<(String, u64)>,
+}
+
+impl Alert {
+    /// Serialize alert to JSON
+    pub fn to_json(&self) -> JsonValue {
+        object!(
+            id: self.id,
+            rule: self.rule.as_str(),
+            metric: self.metric.as_str(),
+            target: self.target.as_str(),
+            state: self.state.as_str(),
+            value: self.value,
+            since: self.since,
+            fired: self.fired,
+            resolved: self.resolved,
+            ackedby: self.acked.as_ref().map(|(user, _)| user.as_str()),
+            ackedat: self.acked.as_ref().map(|(_, time)| *time)
+        )
+    }
+}
+
+/// Alert state change
+#[derive(Clone, Debug)]
+pub enum AlertEvent {
+    /// Alert started firing
+    Fired(Alert),
+
+    /// Firing alert resolved
+    Resolved(Alert),
+}
+
+/// Metric value of a host or server
+pub type Sample = (Metric, String, f64);
+
+/// Alert rules and states
+#[derive(Debug)]
+pub struct Alerts {
+    rules: Vec<Rule>,
+    active: HashMap<(String, String), Alert>,
+    resolved: VecDeque<Alert>,
+    next_id: u64,
+}
+
+impl Alerts {
+    /// Load rules from file, writing default rules if it does not exist
+    pub fn load(file: impl AsRef<Path>) -> Result<Self> {
+        // read or create rules file
+        let file = file.as_ref();
+        let buf = match read_file(file)? {
+            buf if buf.is_empty() => {
+                write_file(file, DEFAULT_RULES.as_bytes())?;
+                DEFAULT_RULES.to_string()
+            }
+            buf => String::from_utf8(buf).or_else(Fail::from)?,
+        };
+
+        // parse rules
+        let json = jzon::parse(&buf).or_else(Fail::from)?;
+        let rules = json.members().map(Rule::parse).collect::<Result<_>>()?;
+        Ok(Self::new(rules))
+    }
+
+    /// Create with rules and no alerts
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules,
+            active: HashMap::new(),
+            resolved: VecDeque::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Configured rules
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Pending and firing alerts, then resolved alerts newest first
+    pub fn list(&self) -> impl Iterator<Item = &Alert> {
+        let mut active: Vec<&Alert> = self.active.values().collect();
+        active.sort_by_key(|alert| alert.id);
+        active.into_iter().chain(self.resolved.iter().rev())
+    }
+
+    /// Acknowledge firing or pending alert
+    pub fn ack(&mut self, id: u64, user: &str, now: u64) -> Result<()> {
+        match self.active.values_mut().find(|alert| alert.id == id) {
+            Some(alert) => {
+                alert.acked = Some((user.to_string(), now));
+                Ok(())
+            }
+            None => Fail::from("alert does not exist or is resolved"),
+        }
+    }
+
+    /// Evaluate rules on samples, returns fired and resolved alerts
+    pub fn evaluate(&mut self, samples: &[Sample], now: u64) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for rule in &self.rules {
+            // current values, servers with alerts that are gone count as offline
+            let mut values: Vec<(&str, f64)> = samples
+                .iter()
+                .filter(|(metric, _, _)| *metric == rule.metric)
+                .map(|(_, target, value)| (target.as_str(), *value))
+                .collect();
+            let (mut gone, mut stale) = (Vec::new(), Vec::new());
+            for (key, alert) in &self.active {
+                if key.0 != rule.name || values.iter().any(|(target, _)| *target == key.1) {
+                    continue;
+                }
+                match alert.state {
+                    // keep unacknowledged alerts of removed servers firing
+                    State::Firing if rule.metric == Metric::Offline && alert.acked.is_none() => {
+                        values.push((key.1.as_str(), 1.0))
+                    }
+                    // resolve alerts of hosts without statistics for a while
+                    State::Firing if rule.metric != Metric::Offline => {
+                        if now.saturating_sub(alert.seen) >= STALE_EVALUATIONS * EVALUATE_INTERVAL {
+                            stale.push(key.clone());
+                        }
+                    }
+                    _ => gone.push(key.clone()),
+                }
+            }
+            let values: Vec<(String, f64)> = values
+                .into_iter()
+                .map(|(target, value)| (target.to_string(), value))
+                .collect();
+            for key in gone {
+                self.active.remove(&key);
+            }
+            for key in stale {
+                let alert = self.active.remove(&key).unwrap();
+                resolve(alert, now, &mut self.resolved, &mut events);
+            }
+
+            // update alerts
+            for (target, value) in values {
+                let key = (rule.name.clone(), target.clone());
+                let Some(alert) = self.active.get_mut(&key) else {
+                    // start pending alert
+                    if rule.triggered(value) {
+                        let alert = Alert {
+                            id: self.next_id,
+                            rule: rule.name.clone(),
+                            metric: rule.metric,
+                            target,
+                            state: State::Pending,
+                            value,
+                            since: now,
+                            seen: now,
+                            fired: None,
+                            resolved: None,
+                            acked: None,
+                        };
+                        self.next_id += 1;
+                        self.active.insert(key.clone(), alert);
+                        if rule.duration == 0 {
+                            let alert = self.active.get_mut(&key).unwrap();
+                            alert.state = State::Firing;
+                            alert.fired = Some(now);
+                            events.push(AlertEvent::Fired(alert.clone()));
+                        }
+                    }
+                    continue;
+                };
+                alert.value = value;
+                alert.seen = now;
+
+                match alert.state {
+                    // fire after duration, drop if no longer triggered
+                    State::Pending if rule.triggered(value) => {
+                        if now.saturating_sub(alert.since) >= rule.duration {
+                            alert.state = State::Firing;
+                            alert.fired = Some(now);
+                            events.push(AlertEvent::Fired(alert.clone()));
+                        }
+                    }
+                    State::Pending => {
+                        self.active.remove(&key);
+                    }
+                    // resolve once cleared
+                    State::Firing if rule.cleared(value) => {
+                        let alert = self.active.remove(&key).unwrap();
+                        resolve(alert, now, &mut self.resolved, &mut events);
+                    }
+                    State::Firing | State::Resolved => {}
+                }
+            }
+        }
+        events
+    }
+}
+
+/// Resolve firing alert and keep it in the resolved list
+fn resolve(
+    mut alert: Alert,
+    now: u64,
+    resolved: &mut VecDeque<Alert>,
+    events: &mut Vec<AlertEvent>,
+) {
+    alert.state = State::Resolved;
+    alert.resolved = Some(now);
+    events.push(AlertEvent::Resolved(alert.clone()));
+    if resolved.len() >= RESOLVED_KEEP {
+        resolved.pop_front();
+    }
+    resolved.push_back(alert);
+}
+
+/// Collect current metric values of hosts and servers
+pub fn collect(shared: &SharedData) -> Vec<Sample> {
+    let percent = |(used, total): (u64, u64)| match total {
+        0 => 0.0,
+        total => used as f64 * 100.0 / total as f64,
+    };
+    let mut samples = Vec::new();
+
+    // host statistics
+    for (host, stats) in shared.statistics().iter() {
+        let sample = stats.sample();
+        samples.push((Metric::Cpu, host.clone(), sample.cpu));
+        samples.push((Metric::Mem, host.clone(), percent(sample.mem)));
+        samples.push((Metric::Disk, host.clone(), percent(sample.disk)));
+        samples.push((Metric::Swap, host.clone(), percent(sample.swap)));
+    }
+
+    // server presence
+    for (name, server) in shared.servers().iter() {
+        let offline = if server.online() { 0.0 } else { 1.0 };
+        samples.push((Metric::Offline, name.clone(), offline));
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Alerts with rule parsed from JSON
+    fn alerts(rule: &str) -> Alerts {
+        Alerts::new(vec![Rule::parse(&jzon::parse(rule).unwrap()).unwrap()])
+    }
+
+    /// Sample of host or server
+    fn sample(metric: Metric, target: &str, value: f64) -> Sample {
+        (metric, target.to_string(), value)
+    }
+
+    /// States of listed alerts
+    fn states(alerts: &Alerts) -> Vec<State> {
+        alerts.list().map(|alert| alert.state).collect()
+    }
+
+    #[test]
+    fn fires_after_duration_and_resolves_with_hysteresis() {
+        let mut alerts =
+            alerts(r#"{"name":"cpu","metric":"cpu","threshold":90,"clear":80,"for":20}"#);
+        let cpu = |value| [sample(Metric::Cpu, "host", value)];
+
+        // pending until triggered for the duration
+        assert!(alerts.evaluate(&cpu(95.0), 0).is_empty());
+        assert_eq!(states(&alerts), [State::Pending]);
+        assert!(alerts.evaluate(&cpu(95.0), 10).is_empty());
+        let events = alerts.evaluate(&cpu(95.0), 20);
+        assert!(matches!(&events[..], [AlertEvent::Fired(alert)] if alert.fired == Some(20)));
+
+        // keeps firing between clear and threshold
+        assert!(alerts.evaluate(&cpu(85.0), 30).is_empty());
+        assert_eq!(states(&alerts), [State::Firing]);
+
+        // resolves at clear level
+        let events = alerts.evaluate(&cpu(80.0), 40);
+        assert!(matches!(&events[..], [AlertEvent::Resolved(alert)] if alert.resolved == Some(40)));
+        assert_eq!(states(&alerts), [State::Resolved]);
+    }
+
+    #[test]
+    fn pending_dropped_when_no_longer_triggered() {
+        let mut alerts =
+            alerts(r#"{"name":"mem","metric":"mem","op":"<","threshold":10,"for":60}"#);
+        alerts.evaluate(&[sample(Metric::Mem, "host", 5.0)], 0);
+        assert_eq!(states(&alerts), [State::Pending]);
+        assert!(
+            alerts
+                .evaluate(&[sample(Metric::Mem, "host", 15.0)], 10)
+                .is_empty()
+        );
+        assert!(states(&alerts).is_empty());
+    }
+
+    #[test]
+    fn zero_duration_fires_immediately() {
+        let mut alerts = alerts(r#"{"name":"disk","metric":"disk","threshold":90}"#);
+        let events = alerts.evaluate(&[sample(Metric::Disk, "host", 95.0)], 0);
+        assert!(matches!(&events[..], [AlertEvent::Fired(_)]));
+        let events = alerts.evaluate(&[sample(Metric::Disk, "host", 50.0)], 10);
+        assert!(matches!(&events[..], [AlertEvent::Resolved(_)]));
+        let events = alerts.evaluate(&[sample(Metric::Disk, "host", 95.0)], 20);
+        assert!(matches!(&events[..], [AlertEvent::Fired(alert)] if alert.id == 2));
+    }
+
+    #[test]
+    fn removed_server_stays_offline_until_acked() {
+        let mut alerts = alerts(r#"{"name":"offline","metric":"offline"}"#);
+        alerts.evaluate(&[sample(Metric::Offline, "lobby", 1.0)], 0);
+        assert_eq!(states(&alerts), [State::Firing]);
+
+        // server removed from list
+        assert!(alerts.evaluate(&[], 10).is_empty());
+        assert_eq!(states(&alerts), [State::Firing]);
+
+        // dropped once acknowledged
+        let id = alerts.list().next().unwrap().id;
+        alerts.ack(id, "admin", 20).unwrap();
+        assert!(alerts.evaluate(&[], 30).is_empty());
+        assert!(states(&alerts).is_empty());
+        assert!(alerts.ack(id, "admin", 40).is_err());
+    }
+
+    #[test]
+    fn host_without_statistics_resolves() {
+        let mut alerts = alerts(r#"{"name":"cpu","metric":"cpu","threshold":90}"#);
+        alerts.evaluate(&[sample(Metric::Cpu, "host", 95.0)], 0);
+        assert!(alerts.evaluate(&[], 10).is_empty());
+        assert_eq!(states(&alerts), [State::Firing]);
+        let stale = STALE_EVALUATIONS * EVALUATE_INTERVAL;
+        let events = alerts.evaluate(&[], stale);
+        assert!(matches!(&events[..], [AlertEvent::Resolved(_)]));
+        assert_eq!(states(&alerts), [State::Resolved]);
+    }
+}
@@ -0,0 +1,57 @@
+//! Alerts API
+
+use crate::auth::Permission;
+use crate::common::*;
+use crate::history::now_secs;
+use jzon::JsonValue;
+use kern::http::server::HttpRequest;
+use wu::{Fail, Result};
+
+/// List alerts and rules handler
+pub fn list(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // get values
+    let headers = req.headers();
+    let username = get_username(headers)?;
+
+    // get alerts of visible hosts and servers and rules
+    let users = shared.users();
+    let alerts = shared.alerts();
+    let list: Vec<JsonValue> = alerts
+        .list()
+        .filter(|alert| users.allowed(username, Permission::ViewServers, Some(&alert.target)))
+        .map(|alert| alert.to_json())
+        .collect();
+    let rules: Vec<JsonValue> = alerts.rules().iter().map(|rule| rule.to_json()).collect();
+
+    // return alerts list
+    Ok(jsonify(object!(alerts: list, rules: rules)))
+}
+
+/// Acknowledge alert handler
+pub fn ack(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // get values
+    let headers = req.headers();
+    let username = get_username(headers)?;
+    let id = get(headers, "id")?;
+
+    // check permission for host or server of alert, releasing alerts before users
+    let target = shared
+        .alerts()
+        .list()
+        .find(|alert| alert.id == id)
+        .map(|alert| alert.target.clone());
+    let permitted = target.is_none_or(|target| {
+        shared
+            .users()
+            .allowed(username, Permission::AckAlerts, Some(&target))
+    });
+    if !permitted {
+        return Fail::from("forbidden");
+    }
+
+    // acknowledge alert
+    shared.alerts_mut().ack(id, username, now_secs())?;
+
+    // successfully acknowledged
+    Ok(jsonify(object!(error: false)))
+}
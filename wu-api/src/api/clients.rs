@@ -0,0 +1,98 @@
+//! Clients API handling
+
+use crate::SharedData;
+use crate::client_api::registry::Client;
+use crate::common::*;
+use jzon::JsonValue;
+use kern::http::server::HttpRequest;
+use std::collections::HashMap;
+use wu::crypto::random_an;
+use wu::{Fail, Result};
+
+/// Get comma-separated list
+fn get_list(data: &HashMap<String, &str>, key: &str) -> Result<Vec<String>> {
+    Ok(get_str(data, key)?
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Client list handler
+pub fn list(_req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // get clients without keys
+    let mut clients = JsonValue::new_object();
+    shared.clients().clients().iter().for_each(|(id, client)| {
+        clients[id] = object!(servers: client.servers(), handlers: client.handlers());
+    });
+
+    // return clients
+    Ok(jsonify(object!(clients: clients)))
+}
+
+/// Client enrollment handler
+pub fn create(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // get values
+    let headers = req.headers();
+    let client = get_an(headers, "client")?;
+    let servers = get_list(headers, "servers")?;
+    let handlers = get_list(headers, "handlers")?;
+
+    // check if client already exists
+    let mut clients = shared.clients_mut();
+    if clients.get(client).is_some() {
+        return Fail::from("client already exists");
+    }
+
+    // generate key and enroll client
+    let key = random_an(32);
+    clients.insert(client, Client::new(key.clone(), servers, handlers)?)?;
+
+    // return key
+    Ok(jsonify(object!(key: key)))
+}
+
+/// Client deletion handler
+pub fn delete(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // get values
+    let headers = req.headers();
+    let client = get_an(headers, "client")?;
+
+    // delete client
+    match shared.clients_mut().remove(client)? {
+        Some(_) => Ok(jsonify(object!(error: false))),
+        None => Fail::from("client does not exist"),
+    }
+}
+
+/// Change client handler
+pub fn change(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // get values
+    let headers = req.headers();
+    let client = get_an(headers, "client")?;
+    let servers = get_list(headers, "servers");
+    let handlers = get_list(headers, "handlers");
+    let reset_key = get(headers, "resetkey").unwrap_or(false);
+
+    // get existing client
+    let mut clients = shared.clients_mut();
+    let existing = clients
+        .get(client)
+        .ok_or_else(|| Fail::new("client does not exist"))?;
+
+    // change values
+    let key = match reset_key {
+        true => random_an(32),
+        false => existing.key().to_string(),
+    };
+    let servers = servers.unwrap_or_else(|_| existing.servers().to_vec());
+    let handlers = handlers.unwrap_or_else(|_| existing.handlers().to_vec());
+    clients.insert(client, Client::new(key.clone(), servers, handlers)?)?;
+
+    // return new key if reset
+    match reset_key {
+        true => Ok(jsonify(object!(error: false, key: key))),
+        false => Ok(jsonify(object!(error: false))),
+    }
+}
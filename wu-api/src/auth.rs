@@ -0,0 +1,269 @@
+//! Roles and permissions
+
+use crate::accounts::Accounts;
+use crate::common::*;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use wu::{Fail, Result};
+
+/// Action a user can be permitted to do
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Permission {
+    /// List servers, statistics and alerts
+    ViewServers,
+
+    /// Read console output and logs
+    ViewConsole,
+
+    /// Execute console commands
+    Exec,
+
+    /// Acknowledge alerts
+    AckAlerts,
+
+    /// Create, change and delete clients
+    ManageClients,
+
+    /// Create, change and delete users and their grants
+    ManageUsers,
+
+    /// Change settings and test notifications
+    ManageSettings,
+}
+
+/// All permissions
+const ALL: &[Permission] = &[
+    Permission::ViewServers,
+    Permission::ViewConsole,
+    Permission::Exec,
+    Permission::AckAlerts,
+    Permission::ManageClients,
+    Permission::ManageUsers,
+    Permission::ManageSettings,
+];
+
+/// Built-in roles and their permissions
+const ROLES: &[(&str, &[Permission])] = &[
+    ("admin", ALL),
+    (
+        "operator",
+        &[
+            Permission::ViewServers,
+            Permission::ViewConsole,
+            Permission::Exec,
+            Permission::AckAlerts,
+        ],
+    ),
+    (
+        "viewer",
+        &[Permission::ViewServers, Permission::ViewConsole],
+    ),
+];
+
+impl Permission {
+    /// Permission name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ViewServers => "servers.view",
+            Self::ViewConsole => "console.view",
+            Self::Exec => "console.exec",
+            Self::AckAlerts => "alerts.ack",
+            Self::ManageClients => "clients.manage",
+            Self::ManageUsers => "users.manage",
+            Self::ManageSettings => "settings.manage",
+        }
+    }
+}
+
+/// Role or permission granted on all servers or a single one
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Grant {
+    /// Permissions of the role or the single permission
+    permissions: &'static [Permission],
+
+    /// Role or permission name
+    name: &'static str,
+
+    /// Server or host name, all if none
+    server: Option<String>,
+}
+
+impl Grant {
+    /// Parse role or permission like operator or console.exec@survival
+    pub fn parse(grant: &str) -> Result<Self> {
+        // split scope
+        let (name, server) = match grant.split_once('@') {
+            Some((name, server)) if !server.is_empty() => (name, Some(server.to_string())),
+            Some(_) => return Fail::from(format!("grant {grant} has no server")),
+            None => (grant, None),
+        };
+
+        // find role or permission
+        let role = ROLES.iter().find(|(role, _)| *role == name);
+        let permission = ALL.iter().find(|permission| permission.as_str() == name);
+        let (name, permissions) = match (role, permission) {
+            (Some((role, permissions)), _) => (*role, *permissions),
+            (None, Some(permission)) => (permission.as_str(), std::slice::from_ref(permission)),
+            (None, None) => return Fail::from(format!("unknown role or permission {name}")),
+        };
+        Ok(Self {
+            permissions,
+            name,
+            server,
+        })
+    }
+
+    /// Parse comma-separated grants
+    pub fn parse_list(grants: &str) -> Result<Vec<Self>> {
+        grants
+            .split(',')
+            .map(str::trim)
+            .filter(|grant| !grant.is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    /// Permissions of the role or the single permission
+    pub fn permissions(&self) -> &'static [Permission] {
+        self.permissions
+    }
+
+    /// Server the grant is limited to, none for all servers
+    pub fn server(&self) -> Option<&str> {
+        self.server.as_deref()
+    }
+
+    /// Check if grant permits action on server, none for actions not bound to a server
+    pub fn permits(&self, permission: Permission, server: Option<&str>) -> bool {
+        self.permissions.contains(&permission)
+            && (self.server.is_none() || self.server.as_deref() == server)
+    }
+}
+
+impl Display for Grant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.server {
+            Some(server) => write!(f, "{}@{server}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Access required by a handler
+#[derive(Clone, Copy, Debug)]
+pub enum Access {
+    /// No login required
+    Public,
+
+    /// Own account, also while the password has to be changed
+    Account,
+
+    /// Own account
+    Login,
+
+    /// Permission not bound to a server
+    Global(Permission),
+
+    /// Permission on the server or host given by the name parameter
+    Server(Permission),
+
+    /// Permission on any server, the handler checks it on every server it lists or resolves
+    Each(Permission),
+}
+
+/// Verify login and permission required by handler
+pub fn authorize(
+    headers: &HashMap<String, &str>,
+    shared: &SharedData,
+    access: Access,
+) -> Result<()> {
+    // public handlers
+    if let Access::Public = access {
+        return Ok(());
+    }
+
+    // verify login and permission on named server
+    let username = get_username(headers)?;
+    let token = get_str(headers, "token")?;
+    let server = match access {
+        Access::Server(_) => Some(get_str(headers, "name")?),
+        _ => None,
+    };
+    authorize_user(shared, username, token, access, server)
+}
+
+/// Verify login of user and permission, server is the name for server access
+pub fn authorize_user(
+    shared: &SharedData,
+    username: &str,
+    token: &str,
+    access: Access,
+    server: Option<&str>,
+) -> Result<()> {
+    // verify login
+    if !shared.logins().valid(username, token) {
+        return Fail::from("unauthenticated");
+    }
+    check_account(&shared.users(), username, access, server)
+}
+
+/// Check password change requirement and permission of logged in user
+fn check_account(
+    users: &Accounts,
+    username: &str,
+    access: Access,
+    server: Option<&str>,
+) -> Result<()> {
+    // only allow changing the password until it is changed
+    let must_change = users
+        .get(username)
+        .is_some_and(|account| account.must_change_password);
+    if must_change && !matches!(access, Access::Account) {
+        return Fail::from("password change required");
+    }
+
+    // check permission
+    let allowed = match access {
+        Access::Public | Access::Account | Access::Login => true,
+        Access::Global(permission) => users.allowed(username, permission, None),
+        Access::Server(permission) => users.allowed(username, permission, server),
+        Access::Each(permission) => users.allowed_any(username, permission),
+    };
+    match allowed {
+        true => Ok(()),
+        false => Fail::from("forbidden"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::Account;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all};
+    use wu::crypto::{Argon2Params, random_an};
+
+    #[test]
+    fn password_change_required() {
+        // administrator with setup password
+        let dir = temp_dir().join(format!("wu-auth-{}", random_an(8)));
+        create_dir_all(&dir).unwrap();
+        let mut users = Accounts::open(&dir, Argon2Params::default()).unwrap();
+        let mut account = Account::new(String::new(), Grant::parse_list("admin").unwrap());
+        account.must_change_password = true;
+        users.create("admin", account).unwrap();
+
+        // only own account until the password is changed
+        let access = Access::Server(Permission::ViewConsole);
+        assert!(check_account(&users, "admin", access, Some("lobby")).is_err());
+        assert!(check_account(&users, "admin", Access::Login, None).is_err());
+        assert!(check_account(&users, "admin", Access::Account, None).is_ok());
+
+        // everything after changing it
+        users
+            .update("admin", |account| account.must_change_password = false)
+            .unwrap();
+        assert!(check_account(&users, "admin", access, Some("lobby")).is_ok());
+        remove_dir_all(dir).ok();
+    }
+}
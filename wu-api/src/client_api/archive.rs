@@ -0,0 +1,285 @@
+//! Console log archive
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions, create_dir_all, read_dir, remove_file, rename};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wu::{Fail, Result};
+
+/// Default maximum size of a log file before rotation (10 MiB)
+pub const DEFAULT_LOG_SIZE: u64 = 10 << 20;
+
+/// Default number of rotated log files kept per server
+pub const DEFAULT_LOG_KEEP: usize = 10;
+
+/// Locks serializing compression and pruning per log directory
+static MAINTENANCE: Mutex<BTreeMap<PathBuf, Arc<Mutex<()>>>> = Mutex::new(BTreeMap::new());
+
+/// Console log configuration
+#[derive(Clone, Copy, Debug)]
+pub struct LogConfig {
+    /// Size in bytes after which a log file is rotated
+    pub max_size: u64,
+
+    /// Rotated log files kept per server
+    pub keep: usize,
+
+    /// Compress rotated log files with gzip
+    pub compress: bool,
+}
+
+/// Log file of a server, named START.log while written and START-END.log[.gz] when rotated
+#[derive(Clone, Debug)]
+pub struct LogFile {
+    /// File name
+    pub name: String,
+
+    /// Unix time in milliseconds the file was started
+    pub start: u64,
+
+    /// Unix time in milliseconds the file was rotated, none while written
+    pub end: Option<u64>,
+
+    /// File size in bytes
+    pub size: u64,
+
+    /// File is gzip compressed
+    pub compressed: bool,
+}
+
+impl LogFile {
+    /// Parse log file name
+    fn parse(name: &str, size: u64) -> Option<Self> {
+        let (stem, compressed) = match name.strip_suffix(".log.gz") {
+            Some(stem) => (stem, true),
+            None => (name.strip_suffix(".log")?, false),
+        };
+        let (start, end) = match stem.split_once('-') {
+            Some((start, end)) => (start.parse().ok()?, Some(end.parse().ok()?)),
+            None if !compressed => (stem.parse().ok()?, None),
+            None => return None,
+        };
+        Some(Self {
+            name: name.to_string(),
+            start,
+            end,
+            size,
+            compressed,
+        })
+    }
+
+    /// Check if file may contain lines between from and to
+    pub fn overlaps(&self, from: u64, to: u64) -> bool {
+        self.start <= to && self.end.is_none_or(|end| end >= from)
+    }
+}
+
+/// Current unix time in milliseconds
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Directory of a server's console logs, escaping characters unsafe in file names
+pub fn log_dir(data_dir: &str, server: &str) -> PathBuf {
+    let mut escaped = String::with_capacity(server.len());
+    for b in server.bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => escaped.push(b as char),
+            _ => escaped.push_str(&format!("%{b:02X}")),
+        }
+    }
+    Path::new(data_dir).join("logs").join(escaped)
+}
+
+/// List log files oldest first
+pub fn list(dir: &Path) -> Result<Vec<LogFile>> {
+    let mut files = Vec::new();
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(files),
+        Err(err) => return Fail::from(err),
+    };
+    for entry in entries {
+        let entry = entry.or_else(Fail::from)?;
+        let size = entry.metadata().or_else(Fail::from)?.len();
+        if let Some(file) = LogFile::parse(&entry.file_name().to_string_lossy(), size) {
+            files.push(file);
+        }
+    }
+    files.sort_by_key(|file| (file.start, file.end.is_none()));
+    Ok(files)
+}
+
+/// Append lines of log file written between from and to, fails when out exceeds max_len
+pub fn read(
+    dir: &Path,
+    file: &LogFile,
+    from: u64,
+    to: u64,
+    out: &mut String,
+    max_len: usize,
+) -> Result<()> {
+    // open file
+    let raw = File::open(dir.join(&file.name)).or_else(Fail::from)?;
+    let reader: Box<dyn Read> = match file.compressed {
+        true => Box::new(GzDecoder::new(raw)),
+        false => Box::new(raw),
+    };
+
+    // filter lines by timestamp
+    for line in BufReader::new(reader).lines() {
+        let line = line.or_else(Fail::from)?;
+        let time = line.split('\t').next().and_then(|t| t.parse().ok());
+        if time.is_some_and(|time| (from..=to).contains(&time)) {
+            if out.len() + line.len() >= max_len {
+                return Fail::from("log range too large, narrow it down with from and to");
+            }
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    Ok(())
+}
+
+/// Rotating console log writer
+#[derive(Debug)]
+pub struct LogArchive {
+    dir: PathBuf,
+    config: LogConfig,
+    current: Option<(File, u64, u64)>,
+}
+
+impl LogArchive {
+    /// Open log directory, continuing an unrotated log file
+    pub fn open(dir: PathBuf, config: LogConfig) -> Result<Self> {
+        // create directory
+        create_dir_all(&dir).or_else(Fail::from)?;
+
+        // find unrotated file
+        let current = match list(&dir)?.into_iter().rfind(|file| file.end.is_none()) {
+            Some(file) => {
+                let handle = OpenOptions::new()
+                    .append(true)
+                    .open(dir.join(&file.name))
+                    .or_else(Fail::from)?;
+                Some((handle, file.start, file.size))
+            }
+            None => None,
+        };
+
+        // return archive
+        Ok(Self {
+            dir,
+            config,
+            current,
+        })
+    }
+
+    /// Write lines sent to the returned sender in a background thread, stopping on failure
+    pub fn spawn(mut self) -> Sender<String> {
+        let (tx, rx) = channel::<String>();
+        thread::spawn(move || {
+            for line in rx {
+                if let Err(err) = self.write(&line) {
+                    return eprintln!("Console log disabled: {err}");
+                }
+            }
+        });
+        tx
+    }
+
+    /// Append line with current timestamp, rotating the file when full
+    pub fn write(&mut self, line: &str) -> Result<()> {
+        // start new file
+        let now = now_millis();
+        let (file, _, size) = match &mut self.current {
+            Some(current) => current,
+            None => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.dir.join(format!("{now}.log")))
+                    .or_else(Fail::from)?;
+                self.current.insert((file, now, 0))
+            }
+        };
+
+        // write line
+        let entry = format!("{now}\t{}\n", line.trim_end_matches(['\r', '\n']));
+        file.write_all(entry.as_bytes()).or_else(Fail::from)?;
+        *size += entry.len() as u64;
+
+        // rotate
+        if *size >= self.config.max_size {
+            self.rotate(now)?;
+        }
+        Ok(())
+    }
+
+    /// Close current file, compress and prune in background
+    fn rotate(&mut self, end: u64) -> Result<()> {
+        // rename current file
+        let Some((_, start, _)) = self.current.take() else {
+            return Ok(());
+        };
+        let rotated = self.dir.join(format!("{start}-{end}.log"));
+        rename(self.dir.join(format!("{start}.log")), &rotated).or_else(Fail::from)?;
+
+        // compress and prune, one rotation of the directory after another
+        let (dir, config) = (self.dir.clone(), self.config);
+        let lock = MAINTENANCE
+            .lock()
+            .unwrap()
+            .entry(dir.clone())
+            .or_default()
+            .clone();
+        thread::spawn(move || {
+            let _maintenance = lock.lock().unwrap();
+            if let Some(Err(err)) = config.compress.then(|| compress(&rotated)) {
+                eprintln!("Failed to compress {}: {err}", rotated.display());
+            }
+            if let Err(err) = prune(&dir, config.keep) {
+                eprintln!("Failed to prune {}: {err}", dir.display());
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Compress file to FILE.gz and remove it
+fn compress(path: &Path) -> Result<()> {
+    // compress into temporary file
+    let mut gz_name = path.as_os_str().to_owned();
+    gz_name.push(".gz");
+    let mut tmp_name = gz_name.clone();
+    tmp_name.push(".tmp");
+    let tmp = File::create(&tmp_name).or_else(Fail::from)?;
+    let mut encoder = GzEncoder::new(tmp, Compression::default());
+    io::copy(&mut File::open(path).or_else(Fail::from)?, &mut encoder).or_else(Fail::from)?;
+    encoder.finish().or_else(Fail::from)?;
+
+    // replace uncompressed file
+    rename(&tmp_name, &gz_name).or_else(Fail::from)?;
+    remove_file(path).or_else(Fail::from)
+}
+
+/// Remove oldest rotated files beyond keep
+fn prune(dir: &Path, keep: usize) -> Result<()> {
+    let rotated: Vec<LogFile> = list(dir)?
+        .into_iter()
+        .filter(|file| file.end.is_some())
+        .collect();
+    for file in rotated.iter().take(rotated.len().saturating_sub(keep)) {
+        remove_file(dir.join(&file.name)).or_else(Fail::from)?;
+    }
+    Ok(())
+}
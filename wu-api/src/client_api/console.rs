@@ -0,0 +1,141 @@
+//! Console line buffer
+
+use std::collections::VecDeque;
+
+/// Default number of console lines kept per server
+pub const DEFAULT_CONSOLE_LINES: usize = 10000;
+
+/// Ring buffer of console lines numbered from 1
+#[derive(Debug)]
+pub struct Console {
+    lines: VecDeque<String>,
+    first_seq: u64,
+    capacity: usize,
+}
+
+/// Lines read from console
+#[derive(Debug)]
+pub struct ConsolePage<'a> {
+    /// Lines after since, oldest first
+    pub lines: Vec<&'a str>,
+
+    /// Sequence number of the last returned line, or since if none
+    pub last: u64,
+
+    /// Lines after since that were dropped from the buffer
+    pub dropped: u64,
+}
+
+impl Console {
+    /// Create console keeping at most capacity lines
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            first_seq: 1,
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Append line, dropping the oldest line when full, returns its sequence number
+    pub fn push(&mut self, line: impl Into<String>) -> u64 {
+        if self.lines.len() >= self.capacity {
+            self.lines.pop_front();
+            self.first_seq += 1;
+        }
+        self.lines.push_back(line.into());
+        self.last_seq()
+    }
+
+    /// Sequence number of the newest line, 0 if empty
+    pub fn last_seq(&self) -> u64 {
+        self.first_seq + self.lines.len() as u64 - 1
+    }
+
+    /// Read at most limit lines after sequence number since
+    pub fn read(&self, since: u64, limit: usize) -> ConsolePage<'_> {
+        // count lines no longer available
+        let dropped = self.first_seq.saturating_sub(since.saturating_add(1));
+
+        // get lines
+        let skip = since.saturating_sub(self.first_seq - 1) as usize;
+        let lines: Vec<&str> = self
+            .lines
+            .iter()
+            .skip(skip)
+            .take(limit)
+            .map(String::as_str)
+            .collect();
+        let last = match lines.len() {
+            0 => since.min(self.last_seq()),
+            len => self.first_seq + (skip + len) as u64 - 1,
+        };
+
+        ConsolePage {
+            lines,
+            last,
+            dropped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Console with lines 1 to count pushed
+    fn console(capacity: usize, count: usize) -> Console {
+        let mut console = Console::new(capacity);
+        (1..=count).for_each(|n| {
+            console.push(n.to_string());
+        });
+        console
+    }
+
+    #[test]
+    fn pages_with_limit() {
+        let console = console(10, 5);
+        let page = console.read(0, 2);
+        assert_eq!(
+            (page.lines, page.last, page.dropped),
+            (vec!["1", "2"], 2, 0)
+        );
+        let page = console.read(2, 10);
+        assert_eq!(
+            (page.lines, page.last, page.dropped),
+            (vec!["3", "4", "5"], 5, 0)
+        );
+
+        // nothing new
+        let page = console.read(5, 10);
+        assert_eq!((page.lines.len(), page.last, page.dropped), (0, 5, 0));
+    }
+
+    #[test]
+    fn counts_dropped_lines() {
+        let console = console(3, 7);
+        assert_eq!(console.last_seq(), 7);
+        let page = console.read(0, 10);
+        assert_eq!(
+            (page.lines, page.last, page.dropped),
+            (vec!["5", "6", "7"], 7, 4)
+        );
+        let page = console.read(3, 1);
+        assert_eq!((page.lines, page.last, page.dropped), (vec!["5"], 5, 1));
+        assert_eq!(console.read(4, 10).dropped, 0);
+    }
+
+    #[test]
+    fn since_beyond_last() {
+        let empty = Console::new(3);
+        assert_eq!(empty.last_seq(), 0);
+        let page = empty.read(0, 10);
+        assert_eq!((page.lines.len(), page.last, page.dropped), (0, 0, 0));
+
+        // future and maximum sequence numbers
+        let console = console(3, 2);
+        let page = console.read(100, 10);
+        assert_eq!((page.lines.len(), page.last, page.dropped), (0, 2, 0));
+        let page = console.read(u64::MAX, 10);
+        assert_eq!((page.lines.len(), page.last, page.dropped), (0, 2, 0));
+    }
+}
@@ -1,28 +1,108 @@
 //! Add server handler
 
-use crate::client_api::server::ServerBuilder;
+use crate::client_api::archive::{LogArchive, log_dir};
+use crate::client_api::server::{Server, ServerBuilder};
+use crate::client_api::{ClientConfig, reject};
 use crate::common::*;
-use wu::net::Connection;
+use crate::notify::{Event, notify};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::sleep;
+use wu::net::AsyncConnection;
+use wu::protocol::Message;
 
-pub fn add_server(conn: Connection, shared: &SharedData, name: String) {
-    // build server
-    let (server, mut manager) = ServerBuilder::new(conn).build();
+/// Time a disconnected server is kept for its client to reconnect
+const OFFLINE_GRACE: Duration = Duration::from_secs(600);
 
-    {
-        // add server to map
+/// Reason why client can not register server name
+fn taken(servers: &HashMap<String, Server>, name: &str, client: &str) -> Option<String> {
+    match servers.get(name) {
+        Some(server) if server.online() => Some(format!("server {name} is already registered")),
+        Some(server) if server.client() != client => Some(format!(
+            "server {name} is registered by client {}",
+            server.client()
+        )),
+        _ => None,
+    }
+}
+
+pub async fn add_server(
+    mut conn: AsyncConnection,
+    shared: &SharedData,
+    name: String,
+    session: u64,
+    config: ClientConfig,
+) {
+    // check if name is taken
+    let client = conn.id().to_string();
+    let reason = taken(&shared.servers(), &name, &client);
+    if let Some(reason) = reason {
+        return reject(conn, reason).await;
+    }
+
+    // confirm registration
+    if conn.send(&Message::Registered).await.is_err() {
+        return;
+    }
+
+    let mut manager = {
+        // attach to disconnected server or add new server unless name was taken meanwhile
         let mut servers = shared.servers_mut();
-        servers.insert(name.clone(), server);
+        if let Some(reason) = taken(&servers, &name, &client) {
+            return eprintln!("Registration failed: {reason}");
+        }
+        match servers.get(&name) {
+            Some(server) => server.attach(conn, session),
+            None => {
+                let mut builder = ServerBuilder::new(conn)
+                    .session(session)
+                    .console_lines(config.console_lines);
+
+                // open console log archive
+                match LogArchive::open(log_dir(&shared.data_dir(), &name), config.logs) {
+                    Ok(archive) => builder = builder.archive(archive),
+                    Err(err) => eprintln!("Console log for server {name} disabled: {err}"),
+                }
+                let (server, manager) = builder.build();
+                servers.insert(name.clone(), server);
+                notify(Event::registered(&name, &client));
+                manager
+            }
+        }
         // drop write-access
-    }
+    };
 
-    // read from client
-    while let Ok(data) = manager.conn().read() {
-        // update server
+    // read from client until disconnect or missing heartbeats
+    let err = loop {
+        let msg = match manager.conn().recv().await {
+            Ok(msg) => msg,
+            Err(err) => break err,
+        };
         let servers = shared.servers();
-        let mut server_data = servers.get(&name).unwrap().data_mut();
-        server_data.push_str(&String::from_utf8_lossy(&data));
-    }
+        let server = servers.get(&name).unwrap();
+        match msg {
+            // update server, skipping replayed lines
+            Message::Console { seq, line } => server.push_line(seq, &line),
+            // update process resource usage
+            Message::Process(stats) => *server.process_mut() = Some(stats),
+            // acknowledge received lines
+            Message::Heartbeat => {
+                server.send(Message::Ack(server.last_seq())).ok();
+            }
+            _ => {}
+        }
+    };
 
-    // remove server
-    shared.servers_mut().remove(&name);
+    // detach server and remove it unless client reconnects in time
+    eprintln!("Server {name} disconnected: {err}");
+    let attachments = shared.servers().get(&name).unwrap().detach();
+    sleep(OFFLINE_GRACE).await;
+    let mut servers = shared.servers_mut();
+    if servers
+        .get(&name)
+        .is_some_and(|server| server.detached_since(attachments))
+    {
+        servers.remove(&name);
+        notify(Event::unregistered(&name, &client));
+    }
 }
@@ -1,35 +1,58 @@
 //! Server statistics handler
 
+use crate::client_api::reject;
 use crate::common::*;
-use std::convert::TryInto;
-use wu::net::Connection;
+use crate::history::now_secs;
+use wu::net::AsyncConnection;
+use wu::protocol::Message;
+
+pub async fn send_stats(mut conn: AsyncConnection, shared: &SharedData, name: String) {
+    // check if name is taken
+    if shared.statistics().contains_key(&name) {
+        return reject(conn, format!("statistics {name} are already registered")).await;
+    }
+
+    // confirm registration
+    if conn.send(&Message::Registered).await.is_err() {
+        return;
+    }
 
-pub fn send_stats(mut conn: Connection, shared: &SharedData, name: String) {
     {
-        // add statistics to map
+        // add statistics to map unless name was taken meanwhile
         let mut stats = shared.statistics_mut();
+        if stats.contains_key(&name) {
+            return eprintln!("Statistics {name} are already registered");
+        }
         stats.insert(name.clone(), Statistics::new());
         // drop write-access
     }
 
-    // read from client
-    while let Ok(data) = conn.read() {
-        if data.len() == 40 {
-            // get statistics
-            let stats = shared.statistics();
-            let mut cpu = stats.get(&name).unwrap().cpu_mut();
-            let mut mem = stats.get(&name).unwrap().mem_mut();
-            let mut disk = stats.get(&name).unwrap().disk_mut();
-
-            // update statistics
-            *cpu = f64::from_be_bytes((&data[..8]).try_into().unwrap());
-            mem.0 = u64::from_be_bytes((&data[8..16]).try_into().unwrap());
-            mem.1 = u64::from_be_bytes((&data[16..24]).try_into().unwrap());
-            disk.0 = u64::from_be_bytes((&data[24..32]).try_into().unwrap());
-            disk.1 = u64::from_be_bytes((&data[32..]).try_into().unwrap());
+    // read from client until disconnect or missing heartbeats
+    let err = loop {
+        match conn.recv().await {
+            Ok(Message::Stats(sample)) => {
+                // record sample in history
+                shared.history_mut().record(&name, now_secs(), &sample);
+
+                // get statistics
+                let stats = shared.statistics();
+                let statistics = stats.get(&name).unwrap();
+
+                // update statistics
+                *statistics.sample_mut() = *sample;
+            }
+            Ok(Message::Heartbeat) => {
+                // answer heartbeat
+                if let Err(err) = conn.send(&Message::Heartbeat).await {
+                    break err.to_string();
+                }
+            }
+            Ok(_) => {}
+            Err(err) => break err.to_string(),
         }
-    }
+    };
 
-    // remove server
+    // remove statistics
+    eprintln!("Statistics {name} disconnected: {err}");
     shared.statistics_mut().remove(&name);
 }
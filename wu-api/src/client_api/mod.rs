@@ -1,43 +1,99 @@
 //! Client API
 
+pub mod archive;
+pub mod console;
+pub mod registry;
 pub mod server;
 
 mod handlers;
 
 use crate::get_share;
-use std::net::TcpListener;
-use std::sync::Arc;
-use std::thread;
-use wu::crypto::init_aead;
-use wu::net::ConnBuilder;
+use archive::LogConfig;
+use tokio::net::TcpListener;
+use wu::net::{AsyncConnBuilder, AsyncConnection, DEFAULT_READ_TIMEOUT, FrameError};
+use wu::protocol::{Handler, Message, ProtocolError, VERSION};
 use wu::{Fail, Result};
 
+/// Client API configuration
+#[derive(Clone, Copy, Debug)]
+pub struct ClientConfig {
+    /// Maximum size of a single encrypted frame
+    pub max_frame_len: usize,
+
+    /// Console lines kept per server
+    pub console_lines: usize,
+
+    /// Console log archive
+    pub logs: LogConfig,
+}
+
 /// Listen for clients
-pub fn listen_clients(addr: &str, api_key: &str) -> Result<()> {
+pub async fn listen_clients(addr: &str, config: ClientConfig) -> Result<()> {
     // listen
-    let listener = TcpListener::bind(addr).or_else(Fail::from)?;
-    let aead = Arc::new(init_aead(api_key)?);
+    let listener = TcpListener::bind(addr).await.or_else(Fail::from)?;
     println!("API server available on {addr}");
 
     loop {
         // accept connections
-        if let Ok((stream, _)) = listener.accept() {
-            // clone
-            let aead = aead.clone();
+        if let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                // accept connection with key of enrolled client
+                let shared = get_share();
+                let psk_for = |id: &str| shared.clients().get(id).map(|c| c.key().into());
+                let builder = AsyncConnBuilder::from(stream)
+                    .max_frame_len(config.max_frame_len)
+                    .read_timeout(Some(DEFAULT_READ_TIMEOUT));
+                let mut conn = match builder.accept(psk_for).await {
+                    Ok(conn) => conn,
+                    Err(err) => return eprintln!("Client connection failed: {err}"),
+                };
 
-            thread::spawn(move || {
-                // accept connection
-                let mut conn = ConnBuilder::from(stream, &aead).accept().unwrap();
-                let htype = String::from_utf8(conn.read().unwrap()).unwrap();
-                let name = String::from_utf8(conn.read().unwrap()).unwrap();
+                // read registration
+                let (handler, name, session) = match conn.recv().await {
+                    Ok(Message::Register {
+                        handler,
+                        name,
+                        session,
+                    }) => (handler, name, session),
+                    Ok(msg) => {
+                        return eprintln!("Client {} sent {msg:?} before registering", conn.id());
+                    }
+                    Err(err @ FrameError::Protocol(ProtocolError::Version { .. })) => {
+                        // tell client to stop reconnecting
+                        eprintln!("Client {} failed to register: {err}", conn.id());
+                        let msg = Message::Incompatible { version: VERSION };
+                        conn.send(&msg).await.ok();
+                        return;
+                    }
+                    Err(err) => return eprintln!("Client {} failed to register: {err}", conn.id()),
+                };
+
+                // check permissions
+                let id = conn.id().to_string();
+                let htype = handler.as_str();
+                if !shared
+                    .clients()
+                    .get(&id)
+                    .is_some_and(|c| c.allowed(htype, &name))
+                {
+                    let reason = format!("client {id} is not allowed to {htype} {name}");
+                    return reject(conn, reason).await;
+                }
 
                 // handle
-                match htype.as_str() {
-                    "add-server" => handlers::add_server(conn, get_share(), name),
-                    "send-stats" => handlers::send_stats(conn, get_share(), name),
-                    _ => {}
+                match handler {
+                    Handler::AddServer => {
+                        handlers::add_server(conn, shared, name, session, config).await
+                    }
+                    Handler::SendStats => handlers::send_stats(conn, shared, name).await,
                 }
             });
         }
     }
 }
+
+/// Reject registration and log reason
+pub async fn reject(mut conn: AsyncConnection, reason: String) {
+    eprintln!("Registration rejected: {reason}");
+    conn.send(&Message::Rejected { reason }).await.ok();
+}
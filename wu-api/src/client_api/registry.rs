@@ -0,0 +1,132 @@
+//! Client registry
+
+use crate::data::StorageFile;
+use std::collections::HashMap;
+use wu::protocol::Handler;
+use wu::{Fail, Result};
+
+/// Handler types a client can be allowed to use
+pub const HANDLER_TYPES: [&str; 2] = [Handler::AddServer.as_str(), Handler::SendStats.as_str()];
+
+/// Enrolled client
+#[derive(Clone, Debug)]
+pub struct Client {
+    key: String,
+    servers: Vec<String>,
+    handlers: Vec<String>,
+}
+
+impl Client {
+    /// Create new client, fails on unknown handler types
+    pub fn new(key: String, servers: Vec<String>, handlers: Vec<String>) -> Result<Self> {
+        // check handler types
+        if let Some(htype) = handlers
+            .iter()
+            .find(|h| !HANDLER_TYPES.contains(&h.as_str()))
+        {
+            return Fail::from(format!("unknown handler type {htype}"));
+        }
+
+        // return client
+        Ok(Self {
+            key,
+            servers,
+            handlers,
+        })
+    }
+
+    /// Pre-shared key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Allowed server names, `*` allows any
+    pub fn servers(&self) -> &[String] {
+        &self.servers
+    }
+
+    /// Allowed handler types
+    pub fn handlers(&self) -> &[String] {
+        &self.handlers
+    }
+
+    /// Check if client may use handler type for server name
+    pub fn allowed(&self, htype: &str, name: &str) -> bool {
+        self.handlers.iter().any(|h| h == htype)
+            && self.servers.iter().any(|s| s == "*" || s == name)
+    }
+
+    /// Parse client from stored JSON
+    fn parse(value: &str) -> Result<Self> {
+        // parse json
+        let json = jzon::parse(value).or_else(Fail::from)?;
+        let strings = |key: &str| -> Vec<String> {
+            json[key]
+                .members()
+                .filter_map(|m| m.as_str().map(str::to_string))
+                .collect()
+        };
+
+        // get values
+        let key = json["key"]
+            .as_str()
+            .ok_or_else(|| Fail::new("client key missing"))?;
+        Self::new(key.to_string(), strings("servers"), strings("handlers"))
+    }
+
+    /// Serialize client to JSON
+    fn serialize(&self) -> String {
+        object!(key: self.key.as_str(), servers: self.servers.clone(), handlers: self.handlers.clone())
+            .dump()
+    }
+}
+
+/// Registry of enrolled clients
+#[derive(Debug)]
+pub struct ClientRegistry {
+    file: StorageFile,
+    clients: HashMap<String, Client>,
+}
+
+impl ClientRegistry {
+    /// Load registry from storage file
+    pub fn new(file: StorageFile) -> Result<Self> {
+        // parse clients
+        let mut clients = HashMap::new();
+        for (id, value) in file.cache() {
+            let client = Client::parse(value)
+                .or_else(|err| Fail::from(format!("invalid client {id}: {err}")))?;
+            clients.insert(id.clone(), client);
+        }
+
+        // return
+        Ok(Self { file, clients })
+    }
+
+    /// Get client by id (case-insensitive)
+    pub fn get(&self, id: &str) -> Option<&Client> {
+        self.clients.get(&id.to_lowercase())
+    }
+
+    /// All clients
+    pub fn clients(&self) -> &HashMap<String, Client> {
+        &self.clients
+    }
+
+    /// Add or replace client and write to file
+    pub fn insert(&mut self, id: &str, client: Client) -> Result<()> {
+        let id = id.to_lowercase();
+        self.file.cache_mut().insert(id.clone(), client.serialize());
+        self.clients.insert(id, client);
+        self.file.write()
+    }
+
+    /// Remove client and write to file
+    pub fn remove(&mut self, id: &str) -> Result<Option<Client>> {
+        let id = id.to_lowercase();
+        self.file.cache_mut().remove(&id);
+        let client = self.clients.remove(&id);
+        self.file.write()?;
+        Ok(client)
+    }
+}
@@ -1,69 +1,224 @@
 //! Server management
 
-use std::convert::TryInto;
-use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
-use wu::Result;
-use wu::crypto::Aes256Gcm;
-use wu::net::{ConnBuilder, Connection};
+use super::archive::LogArchive;
+use super::console::{Console, DEFAULT_CONSOLE_LINES};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tokio::sync::broadcast::{self, Receiver};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use wu::net::{AsyncConnReader, AsyncConnWriter, AsyncConnection};
+use wu::protocol::{Message, ProcessStats};
+use wu::{Fail, Result};
+
+/// Console lines buffered per stream subscriber
+const LINE_SUBSCRIPTION_LEN: usize = 1024;
 
 /// Server builder
-pub struct ServerBuilder<'a> {
-    conn: Connection<'a>,
+pub struct ServerBuilder {
+    conn: AsyncConnection,
+    session: u64,
+    console_lines: usize,
+    archive: Option<LogArchive>,
 }
 
-impl<'a> ServerBuilder<'a> {
+impl ServerBuilder {
     /// Create new server and manager
-    pub fn new(conn: Connection<'a>) -> Self {
-        Self { conn }
+    pub fn new(conn: AsyncConnection) -> Self {
+        Self {
+            conn,
+            session: 0,
+            console_lines: DEFAULT_CONSOLE_LINES,
+            archive: None,
+        }
+    }
+
+    /// Set client session the console line numbers belong to
+    pub fn session(mut self, session: u64) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Set number of console lines kept
+    pub fn console_lines(mut self, console_lines: usize) -> Self {
+        self.console_lines = console_lines;
+        self
     }
 
-    /// Build server and manager
-    pub fn build(mut self) -> (Server, Manager<'a>) {
-        let port = u16::from_be_bytes(self.conn.read().unwrap().as_slice().try_into().unwrap());
+    /// Write console lines to log archive
+    pub fn archive(mut self, archive: LogArchive) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// Build server and manager, forwarding messages over the client connection
+    pub fn build(self) -> (Server, Manager) {
         let server = Server {
-            data: RwLock::new(String::new()),
-            addr: format!("{}:{}", self.conn.stream_ip(), port),
-            aead: self.conn.crypter_aead().clone(),
+            client: self.conn.id().to_string(),
+            console: RwLock::new(Console::new(self.console_lines)),
+            archive: self.archive.map(LogArchive::spawn),
+            lines: broadcast::channel(LINE_SUBSCRIPTION_LEN).0,
+            sequence: Mutex::new((self.session, 0)),
+            outbound: RwLock::new(None),
+            attachments: AtomicU64::new(0),
+            process: RwLock::new(None),
         };
-
-        let manager = Manager { conn: self.conn };
+        let manager = server.attach(self.conn, self.session);
         (server, manager)
     }
 }
 
+/// Write messages to client until server is detached or connection fails
+async fn forward_messages(mut writer: AsyncConnWriter, mut rx: UnboundedReceiver<Message>) {
+    while let Some(msg) = rx.recv().await {
+        if writer.send(&msg).await.is_err() {
+            break;
+        }
+    }
+}
+
 /// Server representation
 pub struct Server {
-    data: RwLock<String>,
-    addr: String,
-    aead: Aes256Gcm,
+    client: String,
+    console: RwLock<Console>,
+    archive: Option<Sender<String>>,
+    lines: broadcast::Sender<(u64, String)>,
+    sequence: Mutex<(u64, u64)>,
+    outbound: RwLock<Option<UnboundedSender<Message>>>,
+    attachments: AtomicU64,
+    process: RwLock<Option<ProcessStats>>,
 }
 
 impl Server {
-    /// Get console data read-only
-    pub fn data(&self) -> RwLockReadGuard<'_, String> {
-        self.data.read().unwrap()
+    /// Attach client connection, console lines of a new session are numbered from 1
+    pub fn attach(&self, conn: AsyncConnection, session: u64) -> Manager {
+        // restart numbering for new session
+        let mut sequence = self.sequence.lock().unwrap();
+        if sequence.0 != session {
+            *sequence = (session, 0);
+        }
+
+        // forward messages over connection
+        let (reader, writer) = conn.into_split();
+        let (outbound, rx) = unbounded_channel();
+        tokio::spawn(forward_messages(writer, rx));
+        *self.outbound.write().unwrap() = Some(outbound);
+        self.attachments.fetch_add(1, Ordering::SeqCst);
+        Manager { reader }
+    }
+
+    /// Detach client connection, returns attachment count to check for reattachment
+    pub fn detach(&self) -> u64 {
+        *self.outbound.write().unwrap() = None;
+        self.attachments.load(Ordering::SeqCst)
+    }
+
+    /// Check if server is still detached since detach returned attachments
+    pub fn detached_since(&self, attachments: u64) -> bool {
+        !self.online() && self.attachments.load(Ordering::SeqCst) == attachments
+    }
+
+    /// Check if a client connection is attached
+    pub fn online(&self) -> bool {
+        self.outbound.read().unwrap().is_some()
+    }
+
+    /// Client id that registered the server
+    pub fn client(&self) -> &str {
+        &self.client
+    }
+
+    /// Get console read-only
+    pub fn console(&self) -> RwLockReadGuard<'_, Console> {
+        self.console.read().unwrap()
+    }
+
+    /// Get console writeable
+    pub fn console_mut(&self) -> RwLockWriteGuard<'_, Console> {
+        self.console.write().unwrap()
     }
 
-    /// Get console data writeable
-    pub fn data_mut(&self) -> RwLockWriteGuard<'_, String> {
-        self.data.write().unwrap()
+    /// Latest resource usage of the server process, none until first reported
+    pub fn process(&self) -> RwLockReadGuard<'_, Option<ProcessStats>> {
+        self.process.read().unwrap()
+    }
+
+    /// Latest resource usage of the server process writeable
+    pub fn process_mut(&self) -> RwLockWriteGuard<'_, Option<ProcessStats>> {
+        self.process.write().unwrap()
+    }
+
+    /// Append console line unless it was already received, note lost lines
+    pub fn push_line(&self, seq: u64, line: &str) {
+        // skip replayed lines
+        let mut sequence = self.sequence.lock().unwrap();
+        if seq <= sequence.1 {
+            return;
+        }
+
+        // append line
+        let mut console = self.console_mut();
+        let lost = seq - sequence.1 - 1;
+        if lost != 0 {
+            self.publish(&mut console, format!("[wu] {lost} console lines lost\n"));
+        }
+        self.publish(&mut console, line.to_string());
+        sequence.1 = seq;
+        drop(console);
+
+        // archive line in order, written by the archive thread
+        if let Some(archive) = &self.archive {
+            archive.send(line.to_string()).ok();
+        }
+    }
+
+    /// Append line to console and send it to stream subscribers
+    fn publish(&self, console: &mut Console, line: String) {
+        match self.lines.receiver_count() {
+            0 => {
+                console.push(line);
+            }
+            _ => {
+                let seq = console.push(line.clone());
+                self.lines.send((seq, line)).ok();
+            }
+        }
+    }
+
+    /// Subscribe to new console lines with their console sequence numbers
+    pub fn subscribe(&self) -> Receiver<(u64, String)> {
+        self.lines.subscribe()
+    }
+
+    /// Highest console line number received in the current session
+    pub fn last_seq(&self) -> u64 {
+        self.sequence.lock().unwrap().1
     }
 
     /// Send command to server
     pub fn cmd(&self, cmd: String) -> Result<()> {
-        let mut conn = ConnBuilder::new(&self.addr, &self.aead)?.init()?;
-        conn.write(cmd)
+        self.send(Message::Command(cmd))
+    }
+
+    /// Send message to client
+    pub fn send(&self, msg: Message) -> Result<()> {
+        match self.outbound.read().unwrap().as_ref() {
+            Some(outbound) => outbound
+                .send(msg)
+                .or_else(|_| Fail::from("server disconnected")),
+            None => Fail::from("server disconnected"),
+        }
     }
 }
 
 /// Server manager
-pub struct Manager<'a> {
-    conn: Connection<'a>,
+pub struct Manager {
+    reader: AsyncConnReader,
 }
 
-impl<'a> Manager<'a> {
+impl Manager {
     /// Connection to server
-    pub fn conn(&mut self) -> &mut Connection<'a> {
-        &mut self.conn
+    pub fn conn(&mut self) -> &mut AsyncConnReader {
+        &mut self.reader
     }
 }
@@ -2,12 +2,17 @@
 
 pub use crate::utils::*;
 
+use crate::accounts::Accounts;
+use crate::alerts::Alerts;
 use crate::api::logins::UserLogins;
+use crate::client_api::registry::ClientRegistry;
 use crate::client_api::server::Server;
-use crate::data::StorageFile;
+use crate::history::StatsHistory;
+use crate::metrics::Metrics;
 use mysql::{Pool, PooledConn};
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use wu::protocol::HostStats;
 use wu::{Fail, Result};
 
 /// Help output
@@ -16,58 +21,97 @@ Usage: wu-api [OPTIONS]
 String S, Integer I, Boolean B (+Length)
 
 Options:
-  --port       I       Port (4490)
-  --addr       S       IP address ([::])
-  --api-port   I       API Port (PORT + 9)
-  --api-addr   S       API IP address (ADDR)
-  --api-key    S+32    API key (RANDOM)
-  --threads    I       Number of threads to start (2)
-  --data       S       Data directory (data)
-  --cert       S       Path to TLS certificate (DATA_DIR/cert.pem)
-  --key        S       Path to TLS certificate key (DATA_DIR/key.pem)
-  --mysql-addr S       MySQL server address ([::1])
-  --mysql-port I       MySQL server port (3306)
-  --mysql-db   S       MySQL database name (webuniverse)
-  --mysql-user S       MySQL username (webuniverse)
-  --mysql-pass S       MySQL password (webuniverse)";
+  --port          I       Port (4490)
+  --addr          S       IP address ([::])
+  --api-port      I       API Port (PORT + 9)
+  --api-addr      S       API IP address (ADDR)
+  --stream-port   I       Console stream WebSocket port (PORT + 1)
+  --max-frame     I       Maximum client frame size in bytes (1048576)
+  --console-lines I       Console lines kept per server (10000)
+  --log-size      I       Console log size in bytes before rotation (10485760)
+  --log-keep      I       Rotated console logs kept per server (10)
+  --log-compress  B       Compress rotated console logs
+  --metrics-port  I       Separate plain HTTP port for /metrics (off)
+  --metrics-addr  S       IP address of the metrics port (127.0.0.1)
+  --metrics-token S       Bearer token for /metrics, required on the API port (none)
+  --threads       I       Number of threads to start (2)
+  --data          S       Data directory (data)
+  --cert          S       Path to TLS certificate (DATA_DIR/cert.pem)
+  --key           S       Path to TLS certificate key (DATA_DIR/key.pem)
+  --mysql-addr    S       MySQL server address ([::1])
+  --mysql-port    I       MySQL server port (3306)
+  --mysql-db      S       MySQL database name (webuniverse)
+  --mysql-user    S       MySQL username (webuniverse)
+  --mysql-pass    S       MySQL password (webuniverse)
+  --argon2-memory I       Argon2id password hash memory in KiB (19456)
+  --argon2-passes I       Argon2id password hash passes (2)
+  --argon2-lanes  I       Argon2id password hash lanes (1)
+  --init-admin    B       Set password of user admin from stdin and exit
+  --allow-default-admin B Start although user admin has the password admin";
 
 /// Cargo.toml
 pub const CARGO_TOML: &str = include_str!("../Cargo.toml");
 
 /// Data shared between handlers
 pub struct SharedData {
-    users: RwLock<StorageFile>,
+    users: RwLock<Accounts>,
+    clients: RwLock<ClientRegistry>,
     logins: RwLock<UserLogins>,
     data_dir: RwLock<String>,
     servers: Arc<RwLock<HashMap<String, Server>>>,
     statistics: RwLock<HashMap<String, Statistics>>,
+    history: RwLock<StatsHistory>,
+    alerts: RwLock<Alerts>,
+    metrics: Metrics,
     mysql_pool: Pool,
 }
 
 impl SharedData {
     /// Default SharedData
-    pub fn new(users: StorageFile, data_dir: String, mysql_pool: Pool) -> Self {
-        // return default with provided user data
+    pub fn new(
+        users: Accounts,
+        clients: ClientRegistry,
+        data_dir: String,
+        history: StatsHistory,
+        alerts: Alerts,
+        metrics: Metrics,
+        mysql_pool: Pool,
+    ) -> Self {
+        // return default with provided user and client data
         Self {
             users: RwLock::new(users),
+            clients: RwLock::new(clients),
             logins: RwLock::new(UserLogins::new()),
             data_dir: RwLock::new(data_dir),
             servers: Arc::new(RwLock::new(HashMap::new())),
             statistics: RwLock::new(HashMap::new()),
+            history: RwLock::new(history),
+            alerts: RwLock::new(alerts),
+            metrics,
             mysql_pool,
         }
     }
 
-    /// Users database read-only
-    pub fn users(&self) -> RwLockReadGuard<'_, StorageFile> {
+    /// User accounts read-only
+    pub fn users(&self) -> RwLockReadGuard<'_, Accounts> {
         self.users.read().unwrap()
     }
 
-    /// Users database writeable
-    pub fn users_mut(&self) -> RwLockWriteGuard<'_, StorageFile> {
+    /// User accounts writeable
+    pub fn users_mut(&self) -> RwLockWriteGuard<'_, Accounts> {
         self.users.write().unwrap()
     }
 
+    /// Client registry read-only
+    pub fn clients(&self) -> RwLockReadGuard<'_, ClientRegistry> {
+        self.clients.read().unwrap()
+    }
+
+    /// Client registry writeable
+    pub fn clients_mut(&self) -> RwLockWriteGuard<'_, ClientRegistry> {
+        self.clients.write().unwrap()
+    }
+
     /// User logins read-only
     pub fn logins(&self) -> RwLockReadGuard<'_, UserLogins> {
         self.logins.read().unwrap()
@@ -103,6 +147,31 @@ impl SharedData {
         self.statistics.write().unwrap()
     }
 
+    /// Statistics history read-only
+    pub fn history(&self) -> RwLockReadGuard<'_, StatsHistory> {
+        self.history.read().unwrap()
+    }
+
+    /// Statistics history writeable
+    pub fn history_mut(&self) -> RwLockWriteGuard<'_, StatsHistory> {
+        self.history.write().unwrap()
+    }
+
+    /// Alerts read-only
+    pub fn alerts(&self) -> RwLockReadGuard<'_, Alerts> {
+        self.alerts.read().unwrap()
+    }
+
+    /// Alerts writeable
+    pub fn alerts_mut(&self) -> RwLockWriteGuard<'_, Alerts> {
+        self.alerts.write().unwrap()
+    }
+
+    /// API metrics
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
     /// MySQL database connection read-only
     pub fn mysql_conn(&self) -> Result<PooledConn> {
         self.mysql_pool.get_conn().or_else(Fail::from)
@@ -112,53 +181,23 @@ impl SharedData {
 /// Server statistics
 #[derive(Debug, Default)]
 pub struct Statistics {
-    /// CPU usage in percent
-    cpu: RwLock<f64>,
-
-    /// Memory used and total in kB
-    mem: RwLock<(u64, u64)>,
-
-    /// Disk space used and total in kB
-    disk: RwLock<(u64, u64)>,
+    /// Latest host statistics sample
+    sample: RwLock<HostStats>,
 }
 
 impl Statistics {
     /// Create new statistics
     pub fn new() -> Self {
-        Self {
-            cpu: RwLock::new(0.0),
-            mem: RwLock::new((0, 0)),
-            disk: RwLock::new((0, 0)),
-        }
-    }
-
-    /// CPU usage in percent read-only
-    pub fn cpu(&self) -> RwLockReadGuard<'_, f64> {
-        self.cpu.read().unwrap()
-    }
-
-    /// CPU usage in percent writeable
-    pub fn cpu_mut(&self) -> RwLockWriteGuard<'_, f64> {
-        self.cpu.write().unwrap()
-    }
-
-    /// Memory usage in percent read-only
-    pub fn mem(&self) -> RwLockReadGuard<'_, (u64, u64)> {
-        self.mem.read().unwrap()
-    }
-
-    /// Memory usage in percent writeable
-    pub fn mem_mut(&self) -> RwLockWriteGuard<'_, (u64, u64)> {
-        self.mem.write().unwrap()
+        Self::default()
     }
 
-    /// Disk space usage in percent read-only
-    pub fn disk(&self) -> RwLockReadGuard<'_, (u64, u64)> {
-        self.disk.read().unwrap()
+    /// Latest sample read-only
+    pub fn sample(&self) -> RwLockReadGuard<'_, HostStats> {
+        self.sample.read().unwrap()
     }
 
-    /// Disk space usage in percent writeable
-    pub fn disk_mut(&self) -> RwLockWriteGuard<'_, (u64, u64)> {
-        self.disk.write().unwrap()
+    /// Latest sample writeable
+    pub fn sample_mut(&self) -> RwLockWriteGuard<'_, HostStats> {
+        self.sample.write().unwrap()
     }
 }
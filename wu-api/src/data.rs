@@ -1,27 +1,29 @@
 //! Database
 
 use std::collections::HashMap;
-use std::fs::{File, OpenOptions, remove_file, rename};
+use std::fs::{File, OpenOptions, TryLockError, read, rename};
+use std::io::ErrorKind;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 use std::string::ToString;
 use wu::{Fail, Result};
 
 /// Raw data storage file
 #[derive(Debug)]
 pub struct StorageFile {
-    file: File,
+    path: PathBuf,
     cache: HashMap<String, String>,
 }
 
 impl StorageFile {
-    /// Open file or create new
+    /// Read file or create new
     pub fn new(file_name: impl AsRef<str>) -> Result<Self> {
-        // open file and parse
-        let mut file = open_file(file_name)?;
-        let cache = parse(read_file(&mut file)?)?;
+        // parse
+        let path = PathBuf::from(file_name.as_ref());
+        let cache = parse(read_file(&path)?)?;
 
         // return
-        Ok(Self { file, cache })
+        Ok(Self { path, cache })
     }
 
     /// Get map from cache
@@ -38,7 +40,7 @@ impl StorageFile {
     pub fn write(&mut self) -> Result<()> {
         // serialize and write
         let buf = serialize(self.cache())?;
-        write_file(&mut self.file, buf.as_bytes()).or_else(Fail::from)
+        write_file(&self.path, buf.as_bytes())
     }
 }
 
@@ -81,54 +83,74 @@ pub fn serialize(data: &HashMap<String, String>) -> Result<String> {
     Ok(buf)
 }
 
-/// Open file or create new
-pub fn open_file(file_name: impl AsRef<str>) -> Result<File> {
-    // open and return file
-    OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(file_name.as_ref())
-        .or_else(Fail::from)
+/// Append suffix to file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
 }
 
-/// Delete file if exists
-pub fn _delete_file(file_name: impl AsRef<str>) -> Result<()> {
-    // delete file
-    remove_file(file_name.as_ref()).or_else(Fail::from)
+/// Take exclusive advisory lock, held until the file is closed
+pub fn lock_file(path: impl AsRef<Path>) -> Result<File> {
+    // open or create lock file
+    let path = path.as_ref();
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .or_else(Fail::from)?;
+
+    // lock without waiting
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => {
+            Fail::from(format!("{} is locked by another instance", path.display()))
+        }
+        Err(TryLockError::Error(err)) => Fail::from(err),
+    }
 }
 
 /// Move file
-pub fn _move_file(file_name: impl AsRef<str>, new_file_name: impl AsRef<str>) -> Result<()> {
-    // delete file
+pub fn move_file(file_name: impl AsRef<Path>, new_file_name: impl AsRef<Path>) -> Result<()> {
+    // move file
     rename(file_name.as_ref(), new_file_name.as_ref()).or_else(Fail::from)
 }
 
-/// Read data from file
-pub fn read_file(file: &mut File) -> Result<Vec<u8>> {
-    // start from beginning
-    file.rewind()?;
-
-    // create buffer
-    let mut buf = Vec::with_capacity(match file.metadata() {
-        Ok(metadata) => metadata.len() as usize,
-        Err(_) => 8192,
-    });
-
-    // read and return
-    file.read_to_end(&mut buf).or_else(Fail::from)?;
-    Ok(buf)
+/// Read data from file, from its backup if interrupted while replacing or empty if neither exists
+pub fn read_file(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+    for path in [path.to_path_buf(), with_suffix(path, ".bak")] {
+        match read(&path) {
+            Ok(buf) => return Ok(buf),
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Fail::from(err),
+        }
+    }
+    Ok(Vec::new())
 }
 
-/// Write data to file
-pub fn write_file(file: &mut File, data: &[u8]) -> Result<()> {
-    // truncate file
-    file.set_len(0).or_else(Fail::from)?;
-
-    // start from first byte
-    file.rewind()?;
-
-    // write data
-    file.write_all(data).or_else(Fail::from)
+/// Write data to temporary file and replace file, keeping the previous one as backup
+pub fn write_file(path: impl AsRef<Path>, data: &[u8]) -> Result<()> {
+    // write and flush temporary file to disk
+    let path = path.as_ref();
+    let tmp = with_suffix(path, ".tmp");
+    let mut file = File::create(&tmp).or_else(Fail::from)?;
+    file.write_all(data).or_else(Fail::from)?;
+    file.sync_all().or_else(Fail::from)?;
+
+    // keep previous generation and move new file in place
+    if path.exists() {
+        move_file(path, with_suffix(path, ".bak"))?;
+    }
+    move_file(&tmp, path)?;
+
+    // flush renames to disk
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .or_else(Fail::from)
 }
@@ -0,0 +1,330 @@
+//! Statistics history
+
+use crate::data::{read_file, write_file};
+use jzon::JsonValue;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wu::protocol::HostStats;
+use wu::{Fail, Result};
+
+/// Resolution in seconds and number of points of each tier, finest first
+const TIERS: [(u64, usize); 4] = [(10, 360), (60, 1440), (900, 672), (3600, 2160)];
+
+/// Seconds covered by the coarsest tier, hosts without newer points are dropped
+const RETENTION: u64 = TIERS[TIERS.len() - 1].0 * TIERS[TIERS.len() - 1].1 as u64;
+
+/// Interval in seconds in which the history is saved
+pub const SAVE_INTERVAL: u64 = 60;
+
+/// Averaged statistics over a time bucket
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Point {
+    /// Bucket start as unix time in seconds
+    pub time: u64,
+
+    /// CPU usage in percent
+    pub cpu: f64,
+
+    /// Memory used and total in kB
+    pub mem: (f64, f64),
+
+    /// Disk space used and total in kB
+    pub disk: (f64, f64),
+
+    /// Number of merged samples
+    pub count: u64,
+}
+
+impl Point {
+    /// Merge other point into running average
+    fn merge(&mut self, other: &Point) {
+        let total = (self.count + other.count) as f64;
+        let avg = |a: f64, b: f64| (a * self.count as f64 + b * other.count as f64) / total;
+        self.cpu = avg(self.cpu, other.cpu);
+        self.mem = (avg(self.mem.0, other.mem.0), avg(self.mem.1, other.mem.1));
+        self.disk = (
+            avg(self.disk.0, other.disk.0),
+            avg(self.disk.1, other.disk.1),
+        );
+        self.count += other.count;
+    }
+
+    /// Serialize point to JSON
+    pub fn to_json(self) -> JsonValue {
+        object!(
+            time: self.time,
+            cpu: self.cpu,
+            memused: self.mem.0,
+            memtotal: self.mem.1,
+            diskused: self.disk.0,
+            disktotal: self.disk.1
+        )
+    }
+
+    /// Serialize point compactly for storage
+    fn store(&self) -> JsonValue {
+        array![
+            self.time,
+            self.cpu,
+            self.mem.0,
+            self.mem.1,
+            self.disk.0,
+            self.disk.1,
+            self.count
+        ]
+    }
+
+    /// Parse stored point
+    fn load(value: &JsonValue) -> Option<Self> {
+        Some(Self {
+            time: value[0].as_u64()?,
+            cpu: value[1].as_f64()?,
+            mem: (value[2].as_f64()?, value[3].as_f64()?),
+            disk: (value[4].as_f64()?, value[5].as_f64()?),
+            count: value[6].as_u64()?,
+        })
+    }
+}
+
+/// Ring buffers of a host at decreasing resolution
+#[derive(Clone, Debug, Default)]
+struct Series {
+    tiers: [VecDeque<Point>; TIERS.len()],
+}
+
+impl Series {
+    /// Add point to every tier
+    fn record(&mut self, point: Point) {
+        for (tier, (resolution, len)) in self.tiers.iter_mut().zip(TIERS) {
+            let time = point.time - point.time % resolution;
+            match tier.back_mut() {
+                Some(last) if last.time == time => last.merge(&point),
+                Some(last) if last.time > time => {}
+                _ => {
+                    if tier.len() >= len {
+                        tier.pop_front();
+                    }
+                    tier.push_back(Point { time, ..point });
+                }
+            }
+        }
+    }
+
+    /// Unix time in seconds of the newest point
+    fn newest(&self) -> Option<u64> {
+        self.tiers[0].back().map(|point| point.time)
+    }
+
+    /// Points between from and to averaged over resolution, returns used resolution
+    fn query(&self, from: u64, to: u64, resolution: u64) -> (u64, Vec<Point>) {
+        // use finest tier reaching back to from, else coarsest
+        let oldest = |tier: &VecDeque<Point>, len| match tier.len() < len {
+            true => 0,
+            false => tier.front().map_or(0, |p| p.time),
+        };
+        let index = (0..TIERS.len())
+            .find(|&i| oldest(&self.tiers[i], TIERS[i].1) <= from)
+            .unwrap_or(TIERS.len() - 1);
+        let resolution = resolution.max(TIERS[index].0);
+
+        // average points into buckets of resolution
+        let mut points: Vec<Point> = Vec::new();
+        for point in self.tiers[index]
+            .iter()
+            .filter(|p| (from..=to).contains(&p.time))
+        {
+            let time = point.time - point.time % resolution;
+            match points.last_mut() {
+                Some(last) if last.time == time => last.merge(point),
+                _ => points.push(Point { time, ..*point }),
+            }
+        }
+        (resolution, points)
+    }
+}
+
+/// Current unix time in seconds
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Statistics history of all hosts
+#[derive(Debug)]
+pub struct StatsHistory {
+    file: PathBuf,
+    hosts: HashMap<String, Series>,
+}
+
+impl StatsHistory {
+    /// Load history from file or create empty
+    pub fn new(file: impl Into<PathBuf>) -> Result<Self> {
+        // read file
+        let file = file.into();
+        let mut hosts = HashMap::new();
+        let buf = read_file(&file)?;
+        if buf.is_empty() {
+            return Ok(Self { file, hosts });
+        }
+        let buf = String::from_utf8(buf).or_else(Fail::from)?;
+
+        // parse hosts
+        let json = jzon::parse(&buf).or_else(Fail::from)?;
+        for (name, tiers) in json.entries() {
+            let mut series = Series::default();
+            for (tier, stored) in series.tiers.iter_mut().zip(tiers.members()) {
+                tier.extend(stored.members().filter_map(Point::load));
+            }
+            hosts.insert(name.to_string(), series);
+        }
+        Ok(Self { file, hosts })
+    }
+
+    /// Record sample of host
+    pub fn record(&mut self, host: &str, time: u64, stats: &HostStats) {
+        let point = Point {
+            time,
+            cpu: stats.cpu,
+            mem: (stats.mem.0 as f64, stats.mem.1 as f64),
+            disk: (stats.disk.0 as f64, stats.disk.1 as f64),
+            count: 1,
+        };
+        self.hosts
+            .entry(host.to_string())
+            .or_default()
+            .record(point);
+    }
+
+    /// Points of host between from and to, averaged over at least resolution seconds
+    pub fn query(
+        &self,
+        host: &str,
+        from: u64,
+        to: u64,
+        resolution: u64,
+    ) -> Option<(u64, Vec<Point>)> {
+        Some(self.hosts.get(host)?.query(from, to, resolution))
+    }
+
+    /// Drop hosts without points within the retention
+    pub fn prune(&mut self, now: u64) {
+        self.hosts.retain(|_, series| {
+            series
+                .newest()
+                .is_some_and(|newest| now.saturating_sub(newest) < RETENTION)
+        });
+    }
+
+    /// Serialize history, written with save after releasing the lock
+    pub fn snapshot(&self) -> Snapshot {
+        let mut json = JsonValue::new_object();
+        for (name, series) in &self.hosts {
+            let tiers: Vec<JsonValue> = series
+                .tiers
+                .iter()
+                .map(|tier| tier.iter().map(Point::store).collect::<Vec<_>>().into())
+                .collect();
+            json[name.as_str()] = tiers.into();
+        }
+        Snapshot {
+            file: self.file.clone(),
+            buf: json.dump(),
+        }
+    }
+}
+
+/// Serialized statistics history
+pub struct Snapshot {
+    file: PathBuf,
+    buf: String,
+}
+
+impl Snapshot {
+    /// Write history to temporary file and replace
+    pub fn save(&self) -> Result<()> {
+        write_file(&self.file, self.buf.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Point with cpu value
+    fn point(time: u64, cpu: f64) -> Point {
+        Point {
+            time,
+            cpu,
+            count: 1,
+            ..Point::default()
+        }
+    }
+
+    #[test]
+    fn record_merges_buckets() {
+        let mut series = Series::default();
+        series.record(point(100, 10.0));
+        series.record(point(105, 30.0));
+        series.record(point(110, 50.0));
+
+        // points older than the newest bucket of a tier are ignored
+        series.record(point(95, 90.0));
+
+        let tier: Vec<_> = series.tiers[0]
+            .iter()
+            .map(|p| (p.time, p.cpu, p.count))
+            .collect();
+        assert_eq!(tier, [(100, 20.0, 2), (110, 50.0, 1)]);
+        let tier: Vec<_> = series.tiers[1]
+            .iter()
+            .map(|p| (p.time, p.cpu, p.count))
+            .collect();
+        assert_eq!(tier, [(60, 45.0, 4)]);
+    }
+
+    #[test]
+    fn query_downsamples() {
+        let mut series = Series::default();
+        (0..6).for_each(|i| series.record(point(i * 10, i as f64)));
+
+        // finest resolution at least
+        let (resolution, points) = series.query(0, 50, 1);
+        assert_eq!((resolution, points.len()), (10, 6));
+
+        // averaged over requested resolution within range
+        let (resolution, points) = series.query(10, 50, 30);
+        let points: Vec<_> = points.iter().map(|p| (p.time, p.cpu)).collect();
+        assert_eq!((resolution, points), (30, vec![(0, 1.5), (30, 4.0)]));
+    }
+
+    #[test]
+    fn query_selects_tier() {
+        let mut series = Series::default();
+        let (resolution, len) = TIERS[0];
+        let end = resolution * (len as u64 + 100);
+        (0..end)
+            .step_by(resolution as usize)
+            .for_each(|time| series.record(point(time, 1.0)));
+        assert_eq!(series.tiers[0].len(), len);
+
+        // recent range from finest tier, older from the next one
+        assert_eq!(series.query(end - 600, end, 1).0, TIERS[0].0);
+        assert_eq!(series.query(0, end, 1).0, TIERS[1].0);
+    }
+
+    #[test]
+    fn prune_removed_hosts() {
+        let mut history = StatsHistory {
+            file: PathBuf::new(),
+            hosts: HashMap::new(),
+        };
+        let stats = HostStats::default();
+        history.record("old", 0, &stats);
+        history.record("new", RETENTION, &stats);
+        history.prune(RETENTION + 10);
+        assert!(history.query("old", 0, u64::MAX, 1).is_none());
+        assert!(history.query("new", 0, u64::MAX, 1).is_some());
+    }
+}
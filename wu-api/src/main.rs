@@ -4,25 +4,47 @@
 #[macro_use]
 extern crate jzon;
 
+mod accounts;
+mod alerts;
 mod api;
+mod auth;
 mod client_api;
 mod common;
 mod data;
+mod history;
+mod metrics;
+mod notify;
+mod stream;
 mod utils;
 
-use client_api::listen_clients;
+use accounts::{Account, Accounts, password_hash, password_verify};
+use alerts::{Alerts, EVALUATE_INTERVAL, collect};
+use auth::{Access, Grant, Permission, authorize};
+use client_api::archive::{DEFAULT_LOG_KEEP, DEFAULT_LOG_SIZE, LogConfig};
+use client_api::console::DEFAULT_CONSOLE_LINES;
+use client_api::registry::ClientRegistry;
+use client_api::{ClientConfig, listen_clients};
 pub use common::*;
-use data::StorageFile;
+use data::{StorageFile, lock_file};
+use history::{SAVE_INTERVAL, StatsHistory, now_secs};
 use kern::http::server::{HttpRequest, HttpServerBuilder};
+use metrics::{Metrics, handle_metrics};
 use mysql::Pool;
+use notify::{Event, notify};
 use std::env::args;
 use std::fs::create_dir;
+use std::io::{Write, stdin, stdout};
+use std::process::exit;
 use std::sync::OnceLock;
-use wu::crypto::{argon2_hash, hash_password};
-use wu::crypto::{random, random_an};
+use std::thread;
+use std::time::Duration;
+use stream::listen_streams;
+use tokio::runtime::Runtime;
+use wu::crypto::{Argon2Params, hash_password, random_an};
 use wu::http::server::{HttpSettings, load_certificate_provider};
+use wu::net::DEFAULT_MAX_FRAME_LEN;
 use wu::{
-    CliBuilder, Result,
+    CliBuilder, Fail, Result,
     meta::{init_name, init_version},
 };
 
@@ -42,7 +64,9 @@ fn main() {
 
     // read cli
     let args: Vec<String> = args().collect();
-    let cmd = CliBuilder::new().options(&["help"]).build(&args);
+    let cmd = CliBuilder::new()
+        .options(&["help", "log-compress", "init-admin", "allow-default-admin"])
+        .build(&args);
     if cmd.option("help") {
         return println!("{HELP}");
     }
@@ -52,7 +76,15 @@ fn main() {
     let addr = cmd.param("addr", "[::]");
     let api_port = cmd.parameter("api-port", port + 9);
     let api_addr = cmd.param("api-addr", addr);
-    let api_key = cmd.parameter("api-key", random_an(32));
+    let stream_port = cmd.parameter("stream-port", port + 1);
+    let max_frame = cmd.parameter("max-frame", DEFAULT_MAX_FRAME_LEN);
+    let console_lines = cmd.parameter("console-lines", DEFAULT_CONSOLE_LINES);
+    let log_size = cmd.parameter("log-size", DEFAULT_LOG_SIZE);
+    let log_keep = cmd.parameter("log-keep", DEFAULT_LOG_KEEP);
+    let log_compress = cmd.option("log-compress");
+    let metrics_port: Option<u16> = cmd.param("metrics-port", "").parse().ok();
+    let metrics_addr = cmd.param("metrics-addr", "127.0.0.1");
+    let metrics_token = cmd.param("metrics-token", "");
     let threads = cmd.parameter("threads", 2);
     let data = cmd.parameter("data", "data".to_string());
     let cert = cmd.parameter("cert", format!("{}/cert.pem", data));
@@ -62,19 +94,60 @@ fn main() {
     let mysql_db = cmd.param("mysql-db", "webuniverse");
     let mysql_user = cmd.param("mysql-user", "webuniverse");
     let mysql_pass = cmd.param("mysql-pass", "webuniverse");
+    let argon2_default = Argon2Params::default();
+    let argon2_params = Argon2Params {
+        memory: cmd.parameter("argon2-memory", argon2_default.memory),
+        iterations: cmd.parameter("argon2-passes", argon2_default.iterations),
+        lanes: cmd.parameter("argon2-lanes", argon2_default.lanes),
+    };
 
-    // open users database
+    // lock data directory against other instances
     create_dir(&data).ok();
-    let mut users = StorageFile::new(format!("{}/users.wdb", data)).unwrap();
-
-    // create admin:admin user if empty
-    if users.cache().is_empty() {
-        users.cache_mut().insert(
-            "admin".to_string(),
-            argon2_hash(hash_password("admin", "admin"), random(16)).unwrap(),
-        );
+    let _lock = lock_file(format!("{}/wu-api.lock", data)).unwrap();
+
+    // open user accounts
+    let mut users = Accounts::open(&data, argon2_params).unwrap();
+
+    // set administrator password and exit
+    if cmd.option("init-admin") {
+        if let Err(err) = init_admin(&mut users) {
+            eprintln!("{err}");
+            exit(1);
+        }
+        return;
+    }
+
+    // create administrator with one-time setup password if empty
+    if users.is_empty() {
+        let setup = random_an(24);
+        let password = password_hash(&hash_password(&setup, "admin"), &users.params()).unwrap();
+        let mut account = Account::new(password, vec![Grant::parse("admin").unwrap()]);
+        account.must_change_password = true;
+        users.create("admin", account).unwrap();
+        println!("Created user admin with one-time setup password {setup}");
+    }
+
+    // refuse default credentials
+    let default = hash_password("admin", "admin");
+    if users
+        .password("admin")
+        .is_some_and(|stored| password_verify(stored, &default))
+    {
+        match cmd.option("allow-default-admin") {
+            true => eprintln!("Warning: user admin still has the default password admin"),
+            false => {
+                eprintln!(
+                    "User admin still has the default password admin, change it with --init-admin or start with --allow-default-admin"
+                );
+                exit(1);
+            }
+        }
     }
 
+    // open clients database
+    let clients = StorageFile::new(format!("{}/clients.wdb", data)).unwrap();
+    let clients = ClientRegistry::new(clients).unwrap();
+
     // connect to MariaDB (old+new)
     /*let mysql_url = format!(
         "mysql://{}:{}@{}:{}/{}",
@@ -88,10 +161,51 @@ fn main() {
         .pass(Some(mysql_pass));
     let mysql_pool = Pool::new(mysql_opts).unwrap();
 
+    // open statistics history
+    let history = StatsHistory::new(format!("{}/stats.json", data)).unwrap();
+
+    // load alert rules
+    let alerts = Alerts::load(format!("{}/alerts.json", data)).unwrap();
+
     // shared data
-    let shared = SharedData::new(users, data, mysql_pool);
+    let metrics_token = Some(metrics_token.to_string()).filter(|token| !token.is_empty());
+    let metrics = Metrics::new(metrics_token, metrics_port.is_some());
+    let shared = SharedData::new(users, clients, data, history, alerts, metrics, mysql_pool);
     SHARED.set(shared).map_err(|_| 0).unwrap();
 
+    // save statistics history and login times periodically
+    thread::spawn(|| {
+        loop {
+            thread::sleep(Duration::from_secs(SAVE_INTERVAL));
+            let snapshot = {
+                let mut history = get_share().history_mut();
+                history.prune(now_secs());
+                history.snapshot()
+            };
+            if let Err(err) = snapshot.save() {
+                eprintln!("Failed to save statistics history: {err}");
+            }
+            if let Err(err) = get_share().users().save_unsaved() {
+                eprintln!("Failed to save login times: {err}");
+            }
+        }
+    });
+
+    // evaluate alert rules periodically
+    thread::spawn(|| {
+        loop {
+            thread::sleep(Duration::from_secs(EVALUATE_INTERVAL));
+            let shared = get_share();
+            let samples = collect(shared);
+            let events = shared.alerts_mut().evaluate(&samples, now_secs());
+            for event in events {
+                let event = Event::alert(&event);
+                eprintln!("{}", event.title);
+                notify(event);
+            }
+        }
+    });
+
     // start HTTPS server
     let tls_config = load_certificate_provider(cert, key).unwrap();
     let settings = HttpSettings::new().threads_num(threads);
@@ -106,39 +220,121 @@ fn main() {
     // print info message
     println!("HTTPS server available on {addr}:{port}");
 
+    // metrics server
+    if let Some(metrics_port) = metrics_port {
+        HttpServerBuilder::new()
+            .addr(format!("{metrics_addr}:{metrics_port}"))
+            .tls_off()
+            .handler(handle_metrics)
+            .build()
+            .unwrap();
+        println!("Metrics available on http://{metrics_addr}:{metrics_port}/metrics");
+    }
+
+    // console streams
+    listen_streams(&format!("{addr}:{stream_port}"), tls_config()).unwrap();
+
     // client api
-    listen_clients(&format!("{api_addr}:{api_port}"), &api_key).unwrap();
+    let config = ClientConfig {
+        max_frame_len: max_frame,
+        console_lines,
+        logs: LogConfig {
+            max_size: log_size,
+            keep: log_keep,
+            compress: log_compress,
+        },
+    };
+    let runtime = Runtime::new().unwrap();
+    runtime
+        .block_on(listen_clients(&format!("{api_addr}:{api_port}"), config))
+        .unwrap();
+}
+
+/// Create administrator or reset its password to one read from stdin
+fn init_admin(users: &mut Accounts) -> Result<()> {
+    // read password
+    print!("New password for admin: ");
+    stdout().flush().or_else(Fail::from)?;
+    let mut password = String::new();
+    stdin().read_line(&mut password).or_else(Fail::from)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() || password == "admin" {
+        return Fail::from("password must not be empty or admin");
+    }
+    let password = password_hash(&hash_password(password, "admin"), &users.params())?;
+
+    // create administrator or reset password, grants and flags
+    let grants = vec![Grant::parse("admin")?];
+    match users.get("admin") {
+        Some(_) => users.update("admin", |account| {
+            account.password = password;
+            account.grants = grants;
+            account.disabled = false;
+            account.must_change_password = false;
+        })?,
+        None => users.create("admin", Account::new(password, grants))?,
+    }
+    println!("Password of user admin set");
+    Ok(())
 }
 
+/// Request handler
+type Handler = fn(HttpRequest, &SharedData) -> Result<Vec<u8>>;
+
 /// Assigning requests to handlers
 fn handle(req: HttpRequest) -> Result<Vec<u8>> {
-    // match url
-    let handler = match req.url() {
+    // match url, lists and alerts check each server themselves, see Access
+    use Access::*;
+    use Permission::*;
+    let shared = get_share();
+    let url = req.url().to_string();
+    let (handler, access): (Handler, Access) = match url.as_str() {
         // user
-        "/user/login" => api::user::login,
-        "/user/delete" => api::user::delete,
-        "/user/logout" => api::user::logout,
-        "/user/valid" => api::user::valid,
-        "/user/update" => api::user::update,
+        "/user/login" => (api::user::login, Public),
+        "/user/delete" => (api::user::delete, Login),
+        "/user/logout" => (api::user::logout, Account),
+        "/user/valid" => (api::user::valid, Public),
+        "/user/update" => (api::user::update, Account),
         // users
-        "/users/create" => api::users::create,
-        "/users/list" => api::users::list,
-        "/users/delete" => api::users::delete,
-        "/users/change" => api::users::change,
+        "/users/create" => (api::users::create, Global(ManageUsers)),
+        "/users/list" => (api::users::list, Global(ManageUsers)),
+        "/users/delete" => (api::users::delete, Global(ManageUsers)),
+        "/users/change" => (api::users::change, Global(ManageUsers)),
+        "/users/grants" => (api::users::grants, Global(ManageUsers)),
+        // clients
+        "/clients/list" => (api::clients::list, Global(ManageClients)),
+        "/clients/create" => (api::clients::create, Global(ManageClients)),
+        "/clients/delete" => (api::clients::delete, Global(ManageClients)),
+        "/clients/change" => (api::clients::change, Global(ManageClients)),
         // servers
-        "/servers/list" => api::servers::list,
-        "/servers/data" => api::servers::data,
-        "/servers/exec" => api::servers::exec,
+        "/servers/list" => (api::servers::list, Each(ViewServers)),
+        "/servers/data" => (api::servers::data, Server(ViewConsole)),
+        "/servers/exec" => (api::servers::exec, Server(Exec)),
+        "/servers/logs/list" => (api::logs::list, Server(ViewConsole)),
+        "/servers/logs/get" => (api::logs::get, Server(ViewConsole)),
         // server
-        "/server/stats" => api::server::stats,
+        "/server/stats" => (api::server::stats, Each(ViewServers)),
+        "/server/stats/history" => (api::server::history, Server(ViewServers)),
+        // alerts
+        "/alerts/list" => (api::alerts::list, Each(ViewServers)),
+        "/alerts/ack" => (api::alerts::ack, Each(AckAlerts)),
+        // notifications
+        "/notify/test" => (api::notify::test, Global(ManageSettings)),
         // settings
-        "/settings/all" => api::settings::all,
-        "/settings/set" => api::settings::set,
+        "/settings/all" => (api::settings::all, Global(ManageSettings)),
+        "/settings/set" => (api::settings::set, Global(ManageSettings)),
+        // metrics
+        "/metrics" if shared.metrics().on_api() => (metrics::metrics, Public),
         _ => return Ok(json_error("handler not found")),
     };
 
-    // handle request
-    Ok(match handler(req, get_share()) {
+    // check access and handle request
+    let resp = match authorize(req.headers(), shared, access) {
+        Ok(()) => handler(req, shared),
+        Err(err) => Err(err),
+    };
+    shared.metrics().request(&url, resp.is_ok());
+    Ok(match resp {
         Ok(resp) => resp,
         Err(err) => json_error(err),
     })
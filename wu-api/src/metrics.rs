@@ -0,0 +1,213 @@
+//! Prometheus metrics
+
+use crate::common::*;
+use kern::http::server::{HttpRequest, ResponseData, respond};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use wu::Result;
+use wu::crypto::ct_eq;
+
+/// Prometheus text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// API internals and access configuration for the metrics endpoint
+#[derive(Debug, Default)]
+pub struct Metrics {
+    token: Option<String>,
+    separate: bool,
+    requests: Mutex<BTreeMap<(String, bool), u64>>,
+    login_failures: AtomicU64,
+}
+
+impl Metrics {
+    /// Create metrics, requiring token as bearer token if given
+    pub fn new(token: Option<String>, separate: bool) -> Self {
+        Self {
+            token,
+            separate,
+            ..Self::default()
+        }
+    }
+
+    /// Metrics are served on the API port, only if not separate and protected by a token
+    pub fn on_api(&self) -> bool {
+        !self.separate && self.token.is_some()
+    }
+
+    /// Count handled API request
+    pub fn request(&self, handler: &str, ok: bool) {
+        let mut requests = self.requests.lock().unwrap();
+        *requests.entry((handler.to_string(), ok)).or_default() += 1;
+    }
+
+    /// Count failed login
+    pub fn login_failure(&self) {
+        self.login_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Render all metrics
+    pub fn render(&self, shared: &SharedData) -> String {
+        // host statistics
+        let (mut cpu, mut mem, mut disk) = (Vec::new(), Vec::new(), Vec::new());
+        for (host, stats) in shared.statistics().iter() {
+            let host = labels(&[("host", host)]);
+            let sample = stats.sample();
+            cpu.push((host.clone(), sample.cpu));
+            mem.push((host.clone(), sample.mem.0 * 1024, sample.mem.1 * 1024));
+            disk.push((host, sample.disk.0 * 1024, sample.disk.1 * 1024));
+        }
+
+        // servers
+        let (mut online, mut lines) = (Vec::new(), Vec::new());
+        for (name, server) in shared.servers().iter() {
+            let server_labels = labels(&[("server", name)]);
+            online.push((server_labels.clone(), server.online() as u64));
+            lines.push((server_labels, server.console().last_seq()));
+        }
+
+        // API internals
+        let requests: Vec<_> = self
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((handler, ok), &count)| {
+                let result = if *ok { "ok" } else { "error" };
+                (labels(&[("handler", handler), ("result", result)]), count)
+            })
+            .collect();
+        let login_failures = [(String::new(), self.login_failures.load(Ordering::Relaxed))];
+
+        // write families
+        let mut out = String::new();
+        let used = |(labels, used, _): &(String, u64, u64)| (labels.clone(), *used);
+        let total = |(labels, _, total): &(String, u64, u64)| (labels.clone(), *total);
+        let f = &mut out;
+        family(
+            f,
+            "wu_host_cpu_usage_percent",
+            "gauge",
+            "Host CPU usage in percent",
+            cpu,
+        );
+        family(
+            f,
+            "wu_host_memory_used_bytes",
+            "gauge",
+            "Host memory used",
+            mem.iter().map(used),
+        );
+        family(
+            f,
+            "wu_host_memory_total_bytes",
+            "gauge",
+            "Host memory total",
+            mem.iter().map(total),
+        );
+        family(
+            f,
+            "wu_host_disk_used_bytes",
+            "gauge",
+            "Host disk space used",
+            disk.iter().map(used),
+        );
+        family(
+            f,
+            "wu_host_disk_total_bytes",
+            "gauge",
+            "Host disk space total",
+            disk.iter().map(total),
+        );
+        family(
+            f,
+            "wu_server_online",
+            "gauge",
+            "Server client connected",
+            online,
+        );
+        family(
+            f,
+            "wu_server_console_lines_total",
+            "counter",
+            "Console lines received",
+            lines,
+        );
+        family(
+            f,
+            "wu_api_requests_total",
+            "counter",
+            "Handled API requests",
+            requests,
+        );
+        family(
+            f,
+            "wu_api_login_failures_total",
+            "counter",
+            "Failed user logins",
+            login_failures,
+        );
+        out
+    }
+}
+
+/// Write metric family with help and type
+fn family(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: impl IntoIterator<Item = (String, impl Display)>,
+) {
+    writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}").ok();
+    for (labels, value) in samples {
+        writeln!(out, "{name}{labels} {value}").ok();
+    }
+}
+
+/// Format label set, escaping values
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<String> = pairs
+        .iter()
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{key}=\"{value}\"")
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+/// Metrics handler, checking the bearer token if configured
+pub fn metrics(req: HttpRequest, shared: &SharedData) -> Result<Vec<u8>> {
+    // verify bearer token
+    let metrics = shared.metrics();
+    if let Some(token) = &metrics.token {
+        let given = req
+            .headers()
+            .get("authorization")
+            .and_then(|auth| auth.strip_prefix("Bearer "));
+        if !given.is_some_and(|given| ct_eq(given.as_bytes(), token.as_bytes())) {
+            let data = ResponseData::unauthorized().header("www-authenticate", "Bearer");
+            return Ok(respond("unauthenticated\n", "text/plain", data.build()));
+        }
+    }
+
+    // render metrics
+    Ok(respond(metrics.render(shared), CONTENT_TYPE, None))
+}
+
+/// Handler for the separate metrics port
+pub fn handle_metrics(req: HttpRequest) -> Result<Vec<u8>> {
+    match req.url() {
+        "/metrics" => metrics(req, crate::get_share()),
+        _ => Ok(respond(
+            "not found\n",
+            "text/plain",
+            ResponseData::not_found().build(),
+        )),
+    }
+}
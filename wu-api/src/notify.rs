@@ -0,0 +1,706 @@
+//! Notifications
+
+use crate::alerts::{Alert, AlertEvent, Metric};
+use crate::common::*;
+use crate::get_share;
+use crate::history::now_secs;
+use jzon::JsonValue;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport};
+use mysql::prelude::*;
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError, sync_channel};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::Duration;
+use ureq::Agent;
+use wu::{Fail, Result};
+
+/// Timeout of a single delivery attempt
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay before the first retry, doubled on every further retry
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Longest delay between retries
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// Retries after a failed delivery if not configured
+const DEFAULT_RETRIES: u32 = 3;
+
+/// Most retries after a failed delivery
+const MAX_RETRIES: u32 = 10;
+
+/// Events waiting for delivery, per notifier, before new ones are dropped
+const QUEUE_LEN: usize = 256;
+
+/// Queue of the dispatching thread
+static QUEUE: OnceLock<SyncSender<Event>> = OnceLock::new();
+
+/// Webhook body if no template is configured
+const DEFAULT_TEMPLATE: &str = r#"{"event":"{{event}}","target":"{{target}}","title":"{{title}}","message":"{{message}}","time":{{time}}}"#;
+
+/// Event sent to notifiers
+#[derive(Clone, Debug)]
+pub struct Event {
+    /// Event kind like alert.fired
+    pub kind: &'static str,
+
+    /// Server or host name
+    pub target: String,
+
+    /// Short summary
+    pub title: String,
+
+    /// Detailed description
+    pub message: String,
+
+    /// Unix time in seconds
+    pub time: u64,
+}
+
+impl Event {
+    /// Create event happening now
+    fn new(kind: &'static str, target: &str, title: String, message: String) -> Self {
+        Self {
+            kind,
+            target: target.to_string(),
+            title,
+            message,
+            time: now_secs(),
+        }
+    }
+
+    /// Client registered new server
+    pub fn registered(server: &str, client: &str) -> Self {
+        let title = format!("Server {server} registered");
+        let message = format!("Client {client} registered server {server}");
+        Self::new("server.registered", server, title, message)
+    }
+
+    /// Disconnected server was removed
+    pub fn unregistered(server: &str, client: &str) -> Self {
+        let title = format!("Server {server} unregistered");
+        let message =
+            format!("Server {server} of client {client} did not reconnect and was removed");
+        Self::new("server.unregistered", server, title, message)
+    }
+
+    /// Alert fired or resolved
+    pub fn alert(event: &AlertEvent) -> Self {
+        let describe = |alert: &Alert, online: &str| match alert.metric {
+            Metric::Offline => format!("Server {} is {online}", alert.target),
+            metric => format!(
+                "{} of {} at {:.1}%",
+                metric.as_str(),
+                alert.target,
+                alert.value
+            ),
+        };
+        match event {
+            AlertEvent::Fired(alert) => Self::new(
+                "alert.fired",
+                &alert.target,
+                format!("Alert {} firing for {}", alert.rule, alert.target),
+                describe(alert, "offline"),
+            ),
+            AlertEvent::Resolved(alert) => Self::new(
+                "alert.resolved",
+                &alert.target,
+                format!("Alert {} resolved for {}", alert.rule, alert.target),
+                describe(alert, "back online"),
+            ),
+        }
+    }
+
+    /// Test notification requested by user
+    pub fn test(user: &str) -> Self {
+        let message = format!("Sent by {user} to verify notification delivery");
+        Self::new("test", "", "Test notification".to_string(), message)
+    }
+
+    /// Fill {{event}}, {{target}}, {{title}}, {{message}} and {{time}} placeholders, escaped for JSON strings
+    fn fill(&self, template: &str) -> String {
+        let escape = |value: &str| {
+            let quoted = JsonValue::from(value).dump();
+            quoted[1..quoted.len() - 1].to_string()
+        };
+        template
+            .replace("{{event}}", self.kind)
+            .replace("{{target}}", &escape(&self.target))
+            .replace("{{title}}", &escape(&self.title))
+            .replace("{{message}}", &escape(&self.message))
+            .replace("{{time}}", &self.time.to_string())
+    }
+}
+
+/// Delivery channel for events
+pub trait Notifier: Send + Sync {
+    /// Channel name
+    fn name(&self) -> &'static str;
+
+    /// Deliver event once
+    fn send(&self, event: &Event) -> Result<()>;
+}
+
+/// POST JSON body to URL
+fn post_json(url: &str, body: &str) -> Result<()> {
+    let agent: Agent = Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .build()
+        .into();
+    agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(body)
+        .or_else(Fail::from)?;
+    Ok(())
+}
+
+/// Generic HTTP webhook with templated JSON body
+struct Webhook {
+    url: String,
+    template: String,
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn send(&self, event: &Event) -> Result<()> {
+        // fill template and make sure it is still valid JSON
+        let body = event.fill(&self.template);
+        if jzon::parse(&body).is_err() {
+            return Fail::from("webhook template is not valid JSON");
+        }
+        post_json(&self.url, &body)
+    }
+}
+
+/// Discord webhook
+struct Discord {
+    url: String,
+}
+
+impl Notifier for Discord {
+    fn name(&self) -> &'static str {
+        "discord"
+    }
+
+    fn send(&self, event: &Event) -> Result<()> {
+        // embed colored by event kind
+        let color = match event.kind {
+            "alert.fired" => 0xe74c3c,
+            "server.unregistered" => 0xe67e22,
+            "alert.resolved" | "server.registered" => 0x2ecc71,
+            _ => 0x3498db,
+        };
+        let body = object!(
+            username: "Webuniverse",
+            embeds: array![object!(
+                title: event.title.as_str(),
+                description: event.message.as_str(),
+                color: color
+            )]
+        );
+        post_json(&self.url, &body.dump())
+    }
+}
+
+/// E-mail via SMTP
+struct Smtp {
+    host: String,
+    port: Option<u16>,
+    security: String,
+    user: String,
+    pass: String,
+    from: String,
+    to: Vec<String>,
+}
+
+impl Notifier for Smtp {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    fn send(&self, event: &Event) -> Result<()> {
+        // build mail
+        let mut builder = lettre::Message::builder()
+            .from(self.from.parse().or_else(Fail::from)?)
+            .subject(&event.title)
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            builder = builder.to(to.parse().or_else(Fail::from)?);
+        }
+        let mail = builder
+            .body(format!("{}\n\nEvent: {}\n", event.message, event.kind))
+            .or_else(Fail::from)?;
+
+        // connect with implicit TLS, STARTTLS or unencrypted
+        let (transport, port) = match self.security.as_str() {
+            "tls" => (SmtpTransport::relay(&self.host).or_else(Fail::from)?, 465),
+            "starttls" => (
+                SmtpTransport::starttls_relay(&self.host).or_else(Fail::from)?,
+                587,
+            ),
+            "none" => (SmtpTransport::builder_dangerous(&self.host), 25),
+            security => return Fail::from(format!("unknown SMTP security {security}")),
+        };
+        let mut transport = transport
+            .port(self.port.unwrap_or(port))
+            .timeout(Some(TIMEOUT));
+        if !self.user.is_empty() {
+            transport =
+                transport.credentials(Credentials::new(self.user.clone(), self.pass.clone()));
+        }
+
+        // send mail
+        transport.build().send(&mail).or_else(Fail::from)?;
+        Ok(())
+    }
+}
+
+/// Notifiers and delivery options from settings
+pub struct NotifyConfig {
+    notifiers: Vec<Arc<dyn Notifier>>,
+    events: Vec<String>,
+    retries: u32,
+}
+
+impl NotifyConfig {
+    /// Load notify_* settings from database
+    pub fn load(shared: &SharedData) -> Result<Self> {
+        let mut conn = shared.mysql_conn()?;
+        let settings: HashMap<String, String> = conn
+            .query("SELECT `key`, `value` FROM settings WHERE `key` LIKE 'notify\\_%'")
+            .or_else(Fail::from)?
+            .into_iter()
+            .collect();
+        Self::parse(&settings)
+    }
+
+    /// Parse settings, notifiers with empty values are disabled
+    pub fn parse(settings: &HashMap<String, String>) -> Result<Self> {
+        let value = |key: &str| {
+            settings
+                .get(&format!("notify_{key}"))
+                .map(|value| value.trim().to_string())
+                .unwrap_or_default()
+        };
+        let list = |key: &str| -> Vec<String> {
+            value(key)
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let mut notifiers: Vec<Arc<dyn Notifier>> = Vec::new();
+
+        // generic webhook
+        let url = value("webhook_url");
+        if !url.is_empty() {
+            let template = match value("webhook_template") {
+                template if template.is_empty() => DEFAULT_TEMPLATE.to_string(),
+                template => template,
+            };
+            notifiers.push(Arc::new(Webhook { url, template }));
+        }
+
+        // discord webhook
+        let url = value("discord_url");
+        if !url.is_empty() {
+            notifiers.push(Arc::new(Discord { url }));
+        }
+
+        // smtp
+        let host = value("smtp_host");
+        if !host.is_empty() {
+            let port = match value("smtp_port") {
+                port if port.is_empty() => None,
+                port => Some(port.parse().or_else(Fail::from)?),
+            };
+            let security = match value("smtp_security") {
+                security if security.is_empty() => "starttls".to_string(),
+                security => security,
+            };
+            let (from, to) = (value("smtp_from"), list("smtp_to"));
+            if from.is_empty() || to.is_empty() {
+                return Fail::from("notify_smtp_from and notify_smtp_to required");
+            }
+            notifiers.push(Arc::new(Smtp {
+                host,
+                port,
+                security,
+                user: value("smtp_user"),
+                pass: value("smtp_pass"),
+                from,
+                to,
+            }));
+        }
+
+        // delivery options
+        let retries = match value("retries") {
+            retries if retries.is_empty() => DEFAULT_RETRIES,
+            retries => retries.parse::<u32>().or_else(Fail::from)?.min(MAX_RETRIES),
+        };
+        Ok(Self {
+            notifiers,
+            events: list("events"),
+            retries,
+        })
+    }
+
+    /// Check if event kind is selected, e.g. by alert or alert.fired, all if none selected
+    fn wants(&self, event: &Event) -> bool {
+        self.events.is_empty()
+            || event.kind == "test"
+            || self.events.iter().any(|selected| {
+                event.kind == selected
+                    || event
+                        .kind
+                        .strip_prefix(selected.as_str())
+                        .is_some_and(|rest| rest.starts_with('.'))
+            })
+    }
+
+    /// Deliver event through every notifier once, returns errors by notifier name
+    pub fn test(&self, event: &Event) -> Vec<(&'static str, Option<String>)> {
+        self.notifiers
+            .iter()
+            .map(|notifier| {
+                let err = notifier.send(event).err().map(|err| err.to_string());
+                (notifier.name(), err)
+            })
+            .collect()
+    }
+}
+
+/// Delivery of an event through one notifier
+struct Job {
+    notifier: Arc<dyn Notifier>,
+    event: Event,
+    retries: u32,
+}
+
+impl Job {
+    /// Deliver event, retrying with backoff
+    fn deliver(&self) {
+        let mut delay = RETRY_DELAY;
+        for attempt in 0..=self.retries {
+            match self.notifier.send(&self.event) {
+                Ok(()) => break,
+                Err(err) if attempt == self.retries => eprintln!(
+                    "Notification {} via {} failed: {err}",
+                    self.event.kind,
+                    self.notifier.name()
+                ),
+                Err(_) => {
+                    thread::sleep(delay);
+                    delay = delay.saturating_mul(2).min(MAX_RETRY_DELAY);
+                }
+            }
+        }
+    }
+}
+
+/// Queue events for delivery in the background, dropped if the queue is full
+pub fn notify(event: Event) {
+    let queue = QUEUE.get_or_init(|| {
+        let (sender, receiver) = sync_channel(QUEUE_LEN);
+        thread::spawn(move || deliver_queued(receiver));
+        sender
+    });
+    if let Err(TrySendError::Full(event)) = queue.try_send(event) {
+        eprintln!("Notification {} dropped, queue is full", event.kind);
+    }
+}
+
+/// Hand queued events to a worker per notifier, loading settings once per batch
+fn deliver_queued(receiver: Receiver<Event>) {
+    let mut workers = HashMap::new();
+    while let Ok(event) = receiver.recv() {
+        let events: Vec<Event> = [event].into_iter().chain(receiver.try_iter()).collect();
+        match NotifyConfig::load(get_share()) {
+            Ok(config) => dispatch(&config, &events, &mut workers),
+            Err(err) => eprintln!("Notifications not sent: {err}"),
+        }
+    }
+}
+
+/// Queue selected events for every notifier, so retries of one do not delay others
+fn dispatch(
+    config: &NotifyConfig,
+    events: &[Event],
+    workers: &mut HashMap<&'static str, SyncSender<Job>>,
+) {
+    for event in events.iter().filter(|event| config.wants(event)) {
+        for notifier in &config.notifiers {
+            // start worker of notifier
+            let worker = workers.entry(notifier.name()).or_insert_with(|| {
+                let (sender, receiver) = sync_channel::<Job>(QUEUE_LEN);
+                thread::spawn(move || receiver.into_iter().for_each(|job| job.deliver()));
+                sender
+            });
+
+            // queue delivery
+            let job = Job {
+                notifier: notifier.clone(),
+                event: event.clone(),
+                retries: config.retries,
+            };
+            if let Err(TrySendError::Full(job)) = worker.try_send(job) {
+                eprintln!(
+                    "Notification {} via {} dropped, queue is full",
+                    job.event.kind,
+                    job.notifier.name()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::time::Instant;
+
+    /// Settings from key value pairs without notify_ prefix
+    fn settings(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (format!("notify_{key}"), value.to_string()))
+            .collect()
+    }
+
+    /// Event of kind
+    fn event(kind: &'static str) -> Event {
+        Event::new(kind, "lobby", "title".to_string(), String::new())
+    }
+
+    /// Answer one HTTP request with status, returns request body
+    fn serve(listener: &TcpListener, status: &str) -> String {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut len = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                len = value.trim().parse().unwrap();
+            }
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body).unwrap();
+        let response =
+            format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+        String::from_utf8(body).unwrap()
+    }
+
+    /// Accept one SMTP session, returns commands and mail data
+    fn serve_smtp(listener: &TcpListener) -> (Vec<String>, String) {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let reply = |reader: &mut BufReader<TcpStream>, line: &str| {
+            let line = format!("{line}\r\n");
+            reader.get_mut().write_all(line.as_bytes()).unwrap();
+        };
+        reply(&mut reader, "220 localhost ESMTP");
+        let (mut commands, mut data) = (Vec::new(), String::new());
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            let command = line.trim_end().to_string();
+            match command.split(' ').next().unwrap().to_uppercase().as_str() {
+                "EHLO" => reply(&mut reader, "250 localhost"),
+                "DATA" => {
+                    reply(&mut reader, "354 end with <CRLF>.<CRLF>");
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        if line == ".\r\n" {
+                            break;
+                        }
+                        data.push_str(&line);
+                    }
+                    reply(&mut reader, "250 queued");
+                }
+                "QUIT" => {
+                    reply(&mut reader, "221 bye");
+                    commands.push(command);
+                    break;
+                }
+                _ => reply(&mut reader, "250 OK"),
+            }
+            commands.push(command);
+        }
+        (commands, data)
+    }
+
+    #[test]
+    fn fill_escapes_values() {
+        let mut event = event("alert.fired");
+        event.target = r#"a "quoted\ name"#.to_string();
+        let json = jzon::parse(&event.fill(DEFAULT_TEMPLATE)).unwrap();
+        assert_eq!(json["event"], "alert.fired");
+        assert_eq!(json["target"], r#"a "quoted\ name"#);
+        assert_eq!(json["time"], event.time);
+    }
+
+    #[test]
+    fn events_selected_by_prefix() {
+        let config = NotifyConfig::parse(&settings(&[("events", "alert, server.unregistered")]));
+        let config = config.unwrap();
+        assert!(config.wants(&event("alert.fired")));
+        assert!(config.wants(&event("alert.resolved")));
+        assert!(config.wants(&event("server.unregistered")));
+        assert!(config.wants(&event("test")));
+        assert!(!config.wants(&event("server.registered")));
+        assert!(!config.wants(&event("alerts.fired")));
+        assert!(
+            NotifyConfig::parse(&settings(&[]))
+                .unwrap()
+                .wants(&event("server.registered"))
+        );
+    }
+
+    #[test]
+    fn parse_settings() {
+        // nothing configured
+        let config = NotifyConfig::parse(&settings(&[])).unwrap();
+        assert!(config.notifiers.is_empty());
+        assert_eq!(config.retries, DEFAULT_RETRIES);
+
+        // every notifier with bounded retries
+        let config = NotifyConfig::parse(&settings(&[
+            ("webhook_url", "http://localhost/hook"),
+            ("discord_url", "http://localhost/discord"),
+            ("smtp_host", "localhost"),
+            ("smtp_from", "wu@localhost"),
+            ("smtp_to", "a@localhost, b@localhost"),
+            ("retries", "1000"),
+        ]))
+        .unwrap();
+        let names: Vec<_> = config.notifiers.iter().map(|n| n.name()).collect();
+        assert_eq!(names, ["webhook", "discord", "smtp"]);
+        assert_eq!(config.retries, MAX_RETRIES);
+
+        // invalid values
+        assert!(NotifyConfig::parse(&settings(&[("smtp_host", "localhost")])).is_err());
+        assert!(NotifyConfig::parse(&settings(&[("retries", "many")])).is_err());
+    }
+
+    #[test]
+    fn webhook_delivered() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let template = r#"{"text":"{{title}} on {{target}}"}"#;
+        let config = NotifyConfig::parse(&settings(&[
+            ("webhook_url", &url),
+            ("webhook_template", template),
+        ]))
+        .unwrap();
+
+        // successful delivery
+        let server = thread::spawn(move || {
+            let body = serve(&listener, "200 OK");
+            (listener, body)
+        });
+        let results = config.test(&event("test"));
+        let (listener, body) = server.join().unwrap();
+        assert_eq!(results, [("webhook", None)]);
+        assert_eq!(body, r#"{"text":"title on lobby"}"#);
+
+        // server error reported
+        let server = thread::spawn(move || serve(&listener, "500 Internal Server Error"));
+        let results = config.test(&event("test"));
+        server.join().unwrap();
+        assert!(results[0].1.is_some());
+    }
+
+    #[test]
+    fn discord_payload() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/api/webhooks/1/token",
+            listener.local_addr().unwrap()
+        );
+        let config = NotifyConfig::parse(&settings(&[("discord_url", &url)])).unwrap();
+        let server = thread::spawn(move || serve(&listener, "204 No Content"));
+        let mut event = event("alert.fired");
+        event.message = "cpu of lobby at 95.0%".to_string();
+        assert_eq!(config.test(&event), [("discord", None)]);
+
+        // one embed colored by kind
+        let body = jzon::parse(&server.join().unwrap()).unwrap();
+        assert_eq!(body["username"], "Webuniverse");
+        assert_eq!(body["embeds"].len(), 1);
+        assert_eq!(body["embeds"][0]["title"], "title");
+        assert_eq!(body["embeds"][0]["description"], "cpu of lobby at 95.0%");
+        assert_eq!(body["embeds"][0]["color"], 0xe74c3c);
+    }
+
+    #[test]
+    fn smtp_delivered() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        let config = NotifyConfig::parse(&settings(&[
+            ("smtp_host", "127.0.0.1"),
+            ("smtp_port", &port),
+            ("smtp_security", "none"),
+            ("smtp_from", "wu@localhost"),
+            ("smtp_to", "a@localhost, b@localhost"),
+        ]))
+        .unwrap();
+        let server = thread::spawn(move || serve_smtp(&listener));
+        assert_eq!(config.test(&event("test")), [("smtp", None)]);
+
+        // envelope for every recipient, then the mail
+        let (commands, data) = server.join().unwrap();
+        assert!(commands[0].starts_with("EHLO "));
+        assert_eq!(
+            commands[1..],
+            [
+                "MAIL FROM:<wu@localhost>",
+                "RCPT TO:<a@localhost>",
+                "RCPT TO:<b@localhost>",
+                "DATA",
+                "QUIT"
+            ]
+        );
+        assert!(data.contains("Subject: title\r\n"));
+        assert!(data.contains("Event: test"));
+    }
+
+    #[test]
+    fn notifiers_retry_independently() {
+        // webhook refuses connections, discord answers
+        let refused = TcpListener::bind("127.0.0.1:0").unwrap();
+        let hook = format!("http://{}/hook", refused.local_addr().unwrap());
+        drop(refused);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let discord = format!("http://{}/discord", listener.local_addr().unwrap());
+        let config = NotifyConfig::parse(&settings(&[
+            ("webhook_url", &hook),
+            ("discord_url", &discord),
+            ("retries", "3"),
+        ]))
+        .unwrap();
+
+        // discord is delivered while the webhook waits to retry
+        let start = Instant::now();
+        dispatch(&config, &[event("alert.fired")], &mut HashMap::new());
+        serve(&listener, "204 No Content");
+        assert!(start.elapsed() < RETRY_DELAY);
+    }
+}
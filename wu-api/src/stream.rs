@@ -0,0 +1,176 @@
+//! Console streaming over WebSocket
+
+use crate::auth::{Access, Permission, authorize_user};
+use crate::get_share;
+use jzon::JsonValue;
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::TryRecvError;
+use tungstenite::{Error as WsError, Message as WsMessage, WebSocket, accept};
+use wu::{Fail, Result};
+
+/// Time to wait for the login message
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time to wait for commands before pushing new lines
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Interval in which the login is checked again
+const LOGIN_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// WebSocket over TLS
+type Socket = WebSocket<StreamOwned<ServerConnection, TcpStream>>;
+
+/// Listen for console streams in background
+pub fn listen_streams(addr: &str, tls_config: Arc<ServerConfig>) -> Result<()> {
+    // listen
+    let listener = TcpListener::bind(addr).or_else(Fail::from)?;
+    println!("Console streams available on {addr}");
+
+    thread::spawn(move || {
+        // accept connections
+        for stream in listener.incoming().flatten() {
+            let tls_config = tls_config.clone();
+            thread::spawn(move || {
+                if let Err(err) = handle(stream, tls_config) {
+                    eprintln!("Console stream failed: {err}");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Accept WebSocket and stream console, reporting errors to the client
+fn handle(stream: TcpStream, tls_config: Arc<ServerConfig>) -> Result<()> {
+    // accept WebSocket over TLS
+    stream
+        .set_read_timeout(Some(LOGIN_TIMEOUT))
+        .or_else(Fail::from)?;
+    let tls = ServerConnection::new(tls_config).or_else(Fail::from)?;
+    let mut socket = accept(StreamOwned::new(tls, stream)).or_else(Fail::from)?;
+
+    // stream and close
+    if let Err(err) = stream_console(&mut socket) {
+        send(&mut socket, object!(error: err.to_string())).ok();
+    }
+    socket.close(None).ok();
+    socket.flush().ok();
+    Ok(())
+}
+
+/// Send JSON text message
+fn send(socket: &mut Socket, value: JsonValue) -> Result<()> {
+    socket
+        .send(WsMessage::text(value.dump()))
+        .or_else(Fail::from)
+}
+
+/// Authenticate, then push console lines and execute commands until closed
+fn stream_console(socket: &mut Socket) -> Result<()> {
+    // read login message
+    let login = match socket.read().or_else(Fail::from)? {
+        WsMessage::Text(text) => jzon::parse(&text).or_else(Fail::from)?,
+        _ => return Fail::from("login message required"),
+    };
+    let field = |key: &str| {
+        login[key]
+            .as_str()
+            .ok_or_else(|| Fail::new(format!("{key} required")))
+    };
+    let (username, token, name) = (field("username")?, field("token")?, field("name")?);
+    let since = login["since"].as_u64().unwrap_or(0);
+
+    // verify login and permission to view console
+    let shared = get_share();
+    let view = Access::Server(Permission::ViewConsole);
+    authorize_user(shared, username, token, view, Some(name))?;
+
+    // subscribe to new lines and get buffered lines
+    let (mut lines, buffered, mut last) = {
+        let servers = shared.servers();
+        let server = servers
+            .get(name)
+            .ok_or_else(|| Fail::new("server does not exist"))?;
+        let lines = server.subscribe();
+        let console = server.console();
+        let page = console.read(since, usize::MAX);
+        let buffered = object!(lines: page.lines, last: page.last, dropped: page.dropped);
+        (lines, buffered, page.last)
+        // drop read-access
+    };
+    send(socket, buffered)?;
+
+    // poll for commands
+    socket
+        .get_ref()
+        .sock
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .or_else(Fail::from)?;
+    let mut login_checked = Instant::now();
+    loop {
+        // collect new lines
+        let mut batch = Vec::new();
+        let mut dropped = 0;
+        loop {
+            match lines.try_recv() {
+                Ok((seq, line)) if seq > last => {
+                    batch.push(line);
+                    last = seq;
+                }
+                Ok(_) => {}
+                Err(TryRecvError::Lagged(count)) => dropped += count,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Closed) => return Fail::from("server does not exist"),
+            }
+        }
+
+        // push new lines
+        if !batch.is_empty() || dropped != 0 {
+            send(socket, object!(lines: batch, last: last, dropped: dropped))?;
+        }
+
+        // check login and permission again
+        if login_checked.elapsed() >= LOGIN_CHECK_INTERVAL {
+            authorize_user(shared, username, token, view, Some(name))?;
+            login_checked = Instant::now();
+        }
+
+        // execute commands
+        match socket.read() {
+            Ok(WsMessage::Text(text)) => {
+                let exec_access = Access::Server(Permission::Exec);
+                let result = authorize_user(shared, username, token, exec_access, Some(name))
+                    .and_then(|_| exec(name, &text));
+                if let Err(err) = result {
+                    send(socket, object!(error: err.to_string()))?;
+                }
+            }
+            Ok(WsMessage::Close(_)) | Err(WsError::ConnectionClosed) => return Ok(()),
+            Ok(_) => {}
+            Err(WsError::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(err) => return Fail::from(err),
+        }
+    }
+}
+
+/// Execute command message on server
+fn exec(name: &str, text: &str) -> Result<()> {
+    // get command
+    let msg = jzon::parse(text).or_else(Fail::from)?;
+    let server_command = msg["command"]
+        .as_str()
+        .ok_or_else(|| Fail::new("command required"))?;
+
+    // send command to execute
+    let servers = get_share().servers();
+    match servers.get(name) {
+        Some(server) => server.cmd(server_command.to_string()),
+        None => Fail::from("server does not exist"),
+    }
+}
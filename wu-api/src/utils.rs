@@ -40,6 +40,16 @@ pub fn get_username<'a>(data: &HashMap<String, &'a str>) -> Result<&'a str> {
     get_an(data, "username")
 }
 
+/// Get optional e-mail address, empty to remove it
+pub fn get_email(data: &HashMap<String, &str>) -> Result<Option<Option<String>>> {
+    match data.get("email") {
+        None => Ok(None),
+        Some(&"") => Ok(Some(None)),
+        Some(email) if email.contains('@') => Ok(Some(Some(email.to_string()))),
+        Some(_) => Fail::from("email is not valid"),
+    }
+}
+
 /// Respond plain
 pub fn respond_plain(plain: impl AsRef<[u8]>) -> Vec<u8> {
     respond(plain, "application/json", cors_headers())
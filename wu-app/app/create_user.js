@@ -4,12 +4,13 @@ load(async function (wasm) {
     document.getElementById("createform").onsubmit = function () {
         const username = document.getElementById("username").value;
         const password = document.getElementById("password").value;
+        const grants = document.getElementById("grants").value;
         if (username == "") {
             return alert("Empty username") == true;
         } else if (password == "") {
             return alert("Empty password") == true;
         }
-        const argon2_hash = wasm.argon2_hash(password, username);
+        const password_hash = wasm.hash_password(password, username);
         api_fetch(async function (json) {
             if (json.error == false) {
                 alert("User successfuly created");
@@ -17,7 +18,7 @@ load(async function (wasm) {
             } else {
                 alert("API error: " + json.error);
             }
-        }, "users/create", { user: username, password: argon2_hash, ...login_data() });
+        }, "users/create", { user: username, password: password_hash, grants, ...login_data() });
         return false;
     };
 });
@@ -1,6 +1,7 @@
-import { load, api_fetch, login_data } from "../js/common.js";
+import { load, config, login_data } from "../js/common.js";
 
-let data_len = 0;
+let last_line = 0;
+let socket;
 
 load(async function (wasm) {
     const get_params = new URLSearchParams(window.location.search);
@@ -18,46 +19,65 @@ load(async function (wasm) {
     consoledata.onmouseout = function () {
         this.mouseIsOver = false;
     }
-    reload_console(name, consoledata);
-    setInterval(function () {
-        reload_console(name, consoledata);
-    }, 1000);
+    stream_console(name, consoledata);
     document.getElementById("serverconsole").onsubmit = function () {
         const server_command_doc = document.getElementById("servercommand");
         const server_command = server_command_doc.value;
         if (server_command == "") {
             return alert("Empty command") == true;
         }
+        if (socket.readyState != WebSocket.OPEN) {
+            return alert("Console not connected") == true;
+        }
         server_command_doc.value = "";
-        api_fetch(async function (json) {
-            if (json.error != false) {
-                alert("API error: " + json.error);
-            }
-        }, "servers/exec", { name, servercommand: server_command, ...login_data() });
+        socket.send(JSON.stringify({ command: server_command }));
         return false;
     };
 });
 
-function reload_console(name, consoledata) {
-    api_fetch(async function (json) {
-        if (json.data != undefined) {
-            if (json.data.length > 0) {
-                data_len = json.len;
-                let temp_data = consoledata.value + json.data;
-                if (temp_data.length > 50000) {
-                    consoledata.value = temp_data.substring(temp_data.length - 50000);
-                } else {
-                    consoledata.value = temp_data;
-                }
-                if (!consoledata.mouseIsOver) {
-                    consoledata.scrollTop = consoledata.scrollHeight;
-                }
-            }
+function stream_console(name, consoledata) {
+    let stopped = false;
+    socket = new WebSocket(config.STREAM_URL);
+    socket.onopen = function () {
+        socket.send(JSON.stringify({ name, since: last_line, ...login_data() }));
+    };
+    socket.onmessage = function (event) {
+        const json = JSON.parse(event.data);
+        if (json.lines != undefined) {
+            append_lines(json, consoledata);
         } else {
             alert("API error: " + json.error);
             if (json.error == "server does not exist" || json.error == "unauthenticated") {
+                stopped = true;
                 location.href = "./servers.html";
             }
         }
-    }, "servers/data", { name, readlen: data_len, ...login_data() });
+    };
+    socket.onclose = function () {
+        if (!stopped) {
+            setTimeout(function () {
+                stream_console(name, consoledata);
+            }, 1000);
+        }
+    };
+}
+
+function append_lines(json, consoledata) {
+    let new_data = json.lines.join("");
+    if (json.dropped > 0 && last_line > 0) {
+        new_data = "[" + json.dropped + " lines dropped]\n" + new_data;
+    }
+    if (json.last < last_line) {
+        consoledata.value = "";
+    }
+    last_line = json.last;
+    let temp_data = consoledata.value + new_data;
+    if (temp_data.length > 50000) {
+        consoledata.value = temp_data.substring(temp_data.length - 50000);
+    } else {
+        consoledata.value = temp_data;
+    }
+    if (!consoledata.mouseIsOver) {
+        consoledata.scrollTop = consoledata.scrollHeight;
+    }
 }
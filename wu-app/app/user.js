@@ -8,6 +8,22 @@ load(async function (wasm) {
         location.href = "./users.html";
         return;
     }
+    api_fetch(async function (json) {
+        const account = json.users != undefined ? json.users.find(account => account.name == user) : undefined;
+        if (account != undefined) {
+            document.getElementById("grants").value = account.grants.join(", ");
+        }
+    }, "users/list", login_data());
+    document.getElementById("savegrants").addEventListener("click", function () {
+        const grants = document.getElementById("grants").value;
+        api_fetch(async function (json) {
+            if (json.error == false) {
+                alert("Grants successfully saved");
+            } else {
+                alert("API error: " + json.error);
+            }
+        }, "users/grants", { user, grants, ...login_data() });
+    });
     document.getElementById("deleteuser").addEventListener("click", function () {
         if (confirm("Delete user?")) {
             api_fetch(async function (json) {
@@ -28,7 +44,7 @@ load(async function (wasm) {
         } else if (new_password.value == "") {
             return alert("Password must be changed when changing the username") == true;
         }
-        const argon2_hash = wasm.argon2_hash(new_password.value, new_username.value != "" ? new_username.value : user);
+        const password_hash = wasm.hash_password(new_password.value, new_username.value != "" ? new_username.value : user);
         api_fetch(async function (json) {
             if (json.error == false) {
                 if (user == username()) {
@@ -39,7 +55,7 @@ load(async function (wasm) {
             } else {
                 alert("API error: " + json.error);
             }
-        }, "users/change", new_username.value != "" && new_password.value != "" ? { newusername: new_username.value, password: argon2_hash, user, ...login_data() } : { password: argon2_hash, user, ...login_data() });
+        }, "users/change", new_username.value != "" && new_password.value != "" ? { newusername: new_username.value, password: password_hash, user, ...login_data() } : { password: password_hash, user, ...login_data() });
         return false;
     };
 });
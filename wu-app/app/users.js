@@ -6,10 +6,11 @@ load(async function (wasm) {
             const users = document.getElementById("userslist");
             for (let i = 0; i < json.users.length; i++) {
                 const a = document.createElement("a");
-                a.innerText = json.users[i];
+                const user = json.users[i];
+                a.innerText = user.name + (user.disabled ? " (disabled)" : "") + (user.grants.length > 0 ? " – " + user.grants.join(", ") : "");
                 a.classList.add("list-group-item");
                 a.classList.add("list-group-item-action");
-                a.href = "./user.html?name=" + json.users[i];
+                a.href = "./user.html?name=" + user.name;
                 users.appendChild(a);
             }
 
@@ -1,2 +1,3 @@
 export const API_URL = "https://localhost:4490";
+export const STREAM_URL = "wss://localhost:4491";
 export const TITLE = "Webuniverse";
@@ -8,6 +8,10 @@ load(async function (wasm) {
             if ("token" in json) {
                 sessionStorage.setItem("username", username);
                 sessionStorage.setItem("token", json.token);
+                if (json.mustchange == true && !(await change_password(wasm, username, json.token))) {
+                    sessionStorage.clear();
+                    return;
+                }
                 location.href = "./app/";
             } else {
                 alert("API error: " + json.error);
@@ -16,3 +20,20 @@ load(async function (wasm) {
         return false;
     };
 }, false);
+
+async function change_password(wasm, username, token) {
+    const new_password = prompt("Password change required, new password:");
+    if (new_password == null || new_password == "") {
+        return false;
+    } else if (new_password != prompt("Repeat new password:")) {
+        alert("Passwords do not match");
+        return false;
+    }
+    const newpassword = wasm.hash_password(new_password, username);
+    return await api_fetch(async function (json) {
+        if (json.error != false) {
+            alert("API error: " + json.error);
+        }
+        return json.error == false;
+    }, "user/update", { username, token, newpassword });
+}
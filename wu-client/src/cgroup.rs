@@ -0,0 +1,283 @@
+//! Control group limits and usage
+
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+/// Limits above this are treated as unlimited (cgroup v1 reports page-aligned i64::MAX)
+const UNLIMITED: u64 = 1 << 62;
+
+/// Pressure stall information of a resource
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pressure {
+    /// Percentage of time some tasks stalled over the last 10 seconds
+    pub some: f64,
+
+    /// Percentage of time all tasks stalled over the last 10 seconds
+    pub full: f64,
+}
+
+/// Control group directories of the current process
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Cgroup {
+    /// Unified hierarchy
+    V2(PathBuf),
+
+    /// Directories per controller
+    V1 {
+        cpu: Option<PathBuf>,
+        cpuacct: Option<PathBuf>,
+        memory: Option<PathBuf>,
+        pids: Option<PathBuf>,
+    },
+}
+
+/// Read file trimmed
+fn read(dir: &Path, file: &str) -> Option<String> {
+    read_to_string(dir.join(file))
+        .ok()
+        .map(|buf| buf.trim().to_string())
+}
+
+/// Read number, none if unlimited
+fn read_limit(dir: &Path, file: &str) -> Option<u64> {
+    read(dir, file)?
+        .parse()
+        .ok()
+        .filter(|&limit| limit < UNLIMITED)
+}
+
+/// Find value of key in flat keyed file like memory.stat
+fn keyed(buf: &str, key: &str) -> Option<u64> {
+    buf.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        (name == key).then(|| value.trim().parse().ok())?
+    })
+}
+
+/// Resolve the cgroup path below a mount, falling back to the mount when namespaced
+fn resolve(mount: PathBuf, path: &str) -> PathBuf {
+    let nested = mount.join(path.trim_start_matches('/'));
+    match nested.is_dir() {
+        true => nested,
+        false => mount,
+    }
+}
+
+/// Parse pressure file, cpu has no full line on older kernels
+pub fn parse_pressure(buf: &str) -> Option<Pressure> {
+    let avg10 = |kind: &str| {
+        buf.lines()
+            .find_map(|line| line.strip_prefix(kind))?
+            .split_ascii_whitespace()
+            .find_map(|field| field.strip_prefix("avg10="))?
+            .parse()
+            .ok()
+    };
+    Some(Pressure {
+        some: avg10("some ")?,
+        full: avg10("full ").unwrap_or(0.0),
+    })
+}
+
+/// Read system-wide pressure from /proc/pressure
+pub fn host_pressure(resource: &str) -> Option<Pressure> {
+    parse_pressure(&read_to_string(format!("/proc/pressure/{resource}")).ok()?)
+}
+
+impl Cgroup {
+    /// Detect control group of the current process
+    pub fn detect() -> Option<Self> {
+        let membership = read_to_string("/proc/self/cgroup").ok()?;
+        Self::parse(&membership, Path::new("/sys/fs/cgroup"))
+    }
+
+    /// Parse /proc/self/cgroup with cgroup filesystem mounted at root
+    pub fn parse(membership: &str, root: &Path) -> Option<Self> {
+        // unified hierarchy
+        if root.join("cgroup.controllers").is_file() {
+            let path = membership
+                .lines()
+                .find_map(|line| line.strip_prefix("0::"))?;
+            return Some(Self::V2(resolve(root.to_path_buf(), path)));
+        }
+
+        // directory of v1 controller, mounted alone or combined like cpu,cpuacct
+        let controller = |name: &str| {
+            membership.lines().find_map(|line| {
+                let mut split = line.splitn(3, ':');
+                let (_, controllers, path) = (split.next()?, split.next()?, split.next()?);
+                if !controllers.split(',').any(|c| c == name) {
+                    return None;
+                }
+                [controllers, name]
+                    .into_iter()
+                    .map(|mount| root.join(mount))
+                    .find(|mount| mount.is_dir())
+                    .map(|mount| resolve(mount, path))
+            })
+        };
+        let cgroup = Self::V1 {
+            cpu: controller("cpu"),
+            cpuacct: controller("cpuacct"),
+            memory: controller("memory"),
+            pids: controller("pids"),
+        };
+        match cgroup {
+            Self::V1 {
+                cpu: None,
+                cpuacct: None,
+                memory: None,
+                pids: None,
+            } => None,
+            cgroup => Some(cgroup),
+        }
+    }
+
+    /// Hierarchy version
+    pub fn version(&self) -> u8 {
+        match self {
+            Self::V2(_) => 2,
+            Self::V1 { .. } => 1,
+        }
+    }
+
+    /// CPU quota in cores, none if unlimited
+    pub fn cpu_quota(&self) -> Option<f64> {
+        let (quota, period) = match self {
+            Self::V2(dir) => {
+                let max = read(dir, "cpu.max")?;
+                let (quota, period) = max.split_once(' ')?;
+                (quota.parse::<f64>().ok()?, period.parse::<f64>().ok()?)
+            }
+            Self::V1 { cpu, .. } => {
+                let cpu = cpu.as_deref()?;
+                let quota: i64 = read(cpu, "cpu.cfs_quota_us")?.parse().ok()?;
+                let period: i64 = read(cpu, "cpu.cfs_period_us")?.parse().ok()?;
+                (quota as f64, period as f64)
+            }
+        };
+        (quota > 0.0 && period > 0.0).then(|| quota / period)
+    }
+
+    /// Total CPU time used in microseconds
+    pub fn cpu_usage(&self) -> Option<u64> {
+        match self {
+            Self::V2(dir) => keyed(&read(dir, "cpu.stat")?, "usage_usec"),
+            Self::V1 { cpuacct, .. } => {
+                let nanos: u64 = read(cpuacct.as_deref()?, "cpuacct.usage")?.parse().ok()?;
+                Some(nanos / 1000)
+            }
+        }
+    }
+
+    /// Memory used without reclaimable page cache and limit in bytes, limit none if unlimited
+    pub fn memory(&self) -> Option<(u64, Option<u64>)> {
+        let (dir, usage, limit, stat, inactive) = match self {
+            Self::V2(dir) => (
+                dir.as_path(),
+                "memory.current",
+                "memory.max",
+                "memory.stat",
+                "inactive_file",
+            ),
+            Self::V1 { memory, .. } => (
+                memory.as_deref()?,
+                "memory.usage_in_bytes",
+                "memory.limit_in_bytes",
+                "memory.stat",
+                "total_inactive_file",
+            ),
+        };
+        let usage: u64 = read(dir, usage)?.parse().ok()?;
+        let inactive = read(dir, stat).and_then(|stat| keyed(&stat, inactive));
+        Some((
+            usage.saturating_sub(inactive.unwrap_or(0)),
+            read_limit(dir, limit),
+        ))
+    }
+
+    /// Number of tasks and limit, limit none if unlimited
+    pub fn pids(&self) -> Option<(u64, Option<u64>)> {
+        let dir = match self {
+            Self::V2(dir) => dir.as_path(),
+            Self::V1 { pids, .. } => pids.as_deref()?,
+        };
+        let current = read(dir, "pids.current")?.parse().ok()?;
+        Some((current, read_limit(dir, "pids.max")))
+    }
+
+    /// Pressure of cpu, memory or io, only available with the unified hierarchy
+    pub fn pressure(&self, resource: &str) -> Option<Pressure> {
+        match self {
+            Self::V2(dir) => parse_pressure(&read(dir, &format!("{resource}.pressure"))?),
+            Self::V1 { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn unified_hierarchy() {
+        let root = fixture("cgroup-v2");
+        let cgroup = Cgroup::parse("0::/docker/abc\n", &root).unwrap();
+        assert_eq!(cgroup, Cgroup::V2(root.join("docker/abc")));
+        assert_eq!(cgroup.cpu_quota(), Some(1.5));
+        assert_eq!(cgroup.cpu_usage(), Some(5_000_000));
+        assert_eq!(
+            cgroup.memory(),
+            Some((536870912 - 134217728, Some(1073741824)))
+        );
+        assert_eq!(cgroup.pids(), Some((12, None)));
+        assert_eq!(
+            cgroup.pressure("cpu"),
+            Some(Pressure {
+                some: 1.5,
+                full: 0.0
+            })
+        );
+        assert_eq!(
+            cgroup.pressure("io"),
+            Some(Pressure {
+                some: 2.0,
+                full: 0.75
+            })
+        );
+        assert_eq!(cgroup.pressure("memory"), None);
+    }
+
+    #[test]
+    fn namespaced_unified_hierarchy() {
+        let root = fixture("cgroup-v2");
+        let cgroup = Cgroup::parse("0::/\n", &root).unwrap();
+        assert_eq!(cgroup, Cgroup::V2(root));
+        assert_eq!(cgroup.cpu_quota(), None);
+    }
+
+    #[test]
+    fn legacy_hierarchy() {
+        let root = fixture("cgroup-v1");
+        let membership = "12:pids:/docker/abc\n4:memory:/docker/abc\n3:cpu,cpuacct:/docker/abc\n1:name=systemd:/docker/abc\n";
+        let cgroup = Cgroup::parse(membership, &root).unwrap();
+        assert_eq!(cgroup.version(), 1);
+        assert_eq!(cgroup.cpu_quota(), None);
+        assert_eq!(cgroup.cpu_usage(), Some(2_500_000));
+        assert_eq!(cgroup.memory(), Some((209715200 - 52428800, None)));
+        assert_eq!(cgroup.pids(), Some((7, Some(512))));
+        assert_eq!(cgroup.pressure("io"), None);
+    }
+
+    #[test]
+    fn missing_hierarchy() {
+        assert_eq!(Cgroup::parse("0::/\n", &fixture("cgroup-none")), None);
+        assert_eq!(parse_pressure("full avg10=1.00\n"), None);
+    }
+}
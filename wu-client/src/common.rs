@@ -12,11 +12,14 @@ Type:
   send-stats      Send server statistics to API
 
 Options:
-  --addr          S       Listener address ([::]:0)
   --api-port      I       API port (4499)
   --api-addr      S       API IP address ([::1])
-  --api-key       S+32    API key (RANDOM)
-  --name          S       Name for server or statistics (RANDOM)";
+  --api-key       S       Client key from /clients/create (RANDOM)
+  --name          S       Name for server or statistics (RANDOM)
+  --client-id     S       Client ID for the API handshake (NAME)
+  --max-backoff   I       Maximum reconnect delay in seconds (60)
+  --spool-lines   I       Console lines kept while disconnected (10000)
+  --mounts        S       Mount points to report, comma separated (ALL local)";
 
 /// Cargo.toml
 pub const CARGO_TOML: &str = include_str!("../Cargo.toml");
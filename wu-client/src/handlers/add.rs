@@ -1,26 +1,27 @@
 //! Add server handler
 
+use crate::link::{Link, Outgoing, forward};
+use crate::process::ProcessSampler;
+use crate::spool::Spool;
 use std::io::BufReader;
 use std::io::prelude::*;
-use std::net::TcpListener;
 use std::process::{Command as Process, Stdio};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use wu::Command;
-use wu::Fail;
-use wu::net::{ConnBuilder, Connection};
+use wu::net::Connection;
+use wu::protocol::Message;
 
-pub fn add_server(mut conn: Connection, cmd: Command, addr: String) {
+/// Interval in which process resource usage is sent
+const PROCESS_INTERVAL: Duration = Duration::from_secs(5);
+
+pub fn add_server(link: Link, mut conn: Connection, cmd: Command) {
     // start process
     let args: &[&str] = match cmd.arguments().len() {
         len if len <= 2 => &[],
         _ => &cmd.arguments()[2..],
     };
-
-    #[allow(unknown_lints)] // fix for older linters
-    #[allow(clippy::zombie_processes)] // is this intended?
     let mut process = Process::new(cmd.arg(1, ""))
         .args(args)
         .stdin(Stdio::piped())
@@ -30,55 +31,62 @@ pub fn add_server(mut conn: Connection, cmd: Command, addr: String) {
         .unwrap();
 
     // init
-    let stdin = Arc::new(RwLock::new(process.stdin.take().unwrap()));
+    let stdin = Arc::new(Mutex::new(process.stdin.take().unwrap()));
     let stdout = process.stdout.take().unwrap();
-    let aead = Arc::new(conn.crypter_aead().clone());
-
-    // listen
-    let listener = TcpListener::bind(addr).or_else(Fail::from).unwrap();
-    conn.write(listener.local_addr().unwrap().port().to_be_bytes())
-        .unwrap();
+    let spool = Arc::new(Spool::new(cmd.parameter("spool-lines", 10000)));
+    let mut sampler = ProcessSampler::new(process.id());
+    let mut next_sample = Instant::now();
 
-    // listener thread
+    // output thread
+    let output = spool.clone();
     thread::spawn(move || {
-        loop {
-            // accept connections
-            if let Ok((stream, _)) = listener.accept() {
-                // clone
-                let stdin = stdin.clone();
-                let aead = aead.clone();
-
-                thread::spawn(move || {
-                    // accept connection and read
-                    let mut conn = ConnBuilder::from(stream, &aead).accept().unwrap();
-                    let read = conn.read().unwrap();
-
-                    // write to process stdin
-                    let mut stdin = stdin.write().unwrap();
-                    stdin.write_all(&read).unwrap();
-                    stdin.write_all(b"\n").unwrap();
-                });
-            }
+        // spool lines until process output ends
+        let mut br = BufReader::new(stdout);
+        let mut buf = Vec::new();
+        while br.read_until(b'\n', &mut buf).is_ok_and(|len| len != 0) {
+            output.push(String::from_utf8_lossy(&buf).to_string());
+            buf.clear();
         }
+        output.close();
     });
 
-    // buffered reader
-    let mut br = BufReader::new(stdout);
-    let mut buf = Vec::new();
+    // forward output and commands, reconnect on connection loss
     loop {
-        // read into buf
-        let read_len = br.read_until(b'\n', &mut buf).unwrap();
+        // replay unacknowledged lines, then follow new output and send process usage
+        let mut sent = 0;
+        let next = |timeout: Duration| {
+            let now = Instant::now();
+            if now >= next_sample {
+                next_sample = now + PROCESS_INTERVAL;
+                return Outgoing::Message(Message::Process(sampler.sample()));
+            }
+            let out = spool.next_after(sent, timeout.min(next_sample - now));
+            if let Outgoing::Message(Message::Console { seq, .. }) = &out {
+                sent = *seq;
+            }
+            out
+        };
 
-        // check if empty
-        if read_len != 0 {
-            // write buffer
-            conn.write(&buf).unwrap();
-        } else {
-            // sleep
-            sleep(Duration::from_millis(25));
-        }
+        // handle commands and acknowledgements
+        let (stdin, spool) = (stdin.clone(), spool.clone());
+        let on_message = move |msg| match msg {
+            Message::Command(read) => {
+                // write to process stdin, ignoring a closed stdin
+                let mut stdin = stdin.lock().unwrap();
+                stdin.write_all(read.as_bytes()).ok();
+                stdin.write_all(b"\n").ok();
+            }
+            Message::Ack(seq) => spool.ack(seq),
+            _ => {}
+        };
 
-        // empty buffer
-        buf.clear();
+        match forward(conn, next, on_message) {
+            Ok(()) => break,
+            Err(err) => eprintln!("Connection to API lost: {err}"),
+        }
+        conn = link.reconnect();
     }
+
+    // wait for process
+    process.wait().unwrap();
 }
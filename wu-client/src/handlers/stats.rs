@@ -1,23 +1,38 @@
 //! Server stats handler
 
-use crate::utils::cpu_mem_usage;
+use crate::link::{Link, Outgoing, forward};
+use crate::utils::host_stats;
+use std::process::exit;
+use std::sync::mpsc::RecvTimeoutError;
 use std::time::Duration;
 use wu::Command;
 use wu::net::Connection;
+use wu::protocol::Message;
 
-pub fn send_stats(mut conn: Connection, _cmd: Command) {
-    let (rx, _) = cpu_mem_usage(Duration::from_secs(5));
+pub fn send_stats(link: Link, mut conn: Connection, cmd: Command) {
+    let mounts = cmd.param("mounts", "").split(',');
+    let mounts = mounts.filter(|path| !path.is_empty()).map(str::to_string);
+    let (samples, sampler) = host_stats(Duration::from_secs(5), mounts.collect());
 
-    let mut buf = Vec::with_capacity(40);
-    while let Ok((cpu_usage, (mem_used, mem_total), (disk_used, disk_total))) =
-        rx.recv_timeout(Duration::from_secs(10))
-    {
-        buf.extend_from_slice(&cpu_usage.to_be_bytes());
-        buf.extend_from_slice(&mem_used.to_be_bytes());
-        buf.extend_from_slice(&mem_total.to_be_bytes());
-        buf.extend_from_slice(&disk_used.to_be_bytes());
-        buf.extend_from_slice(&disk_total.to_be_bytes());
-        conn.write(&buf).unwrap();
-        buf.clear();
+    // forward statistics, reconnect on connection loss
+    loop {
+        let next = |timeout| match samples.recv_timeout(timeout) {
+            Ok(stats) => Outgoing::Message(Message::Stats(Box::new(stats))),
+            Err(RecvTimeoutError::Timeout) => Outgoing::Idle,
+            Err(RecvTimeoutError::Disconnected) => Outgoing::Done,
+        };
+        match forward(conn, next, |_| {}) {
+            Ok(()) => break,
+            Err(err) => eprintln!("Connection to API lost: {err}"),
+        }
+        conn = link.reconnect();
     }
+
+    // sampler only stops on failure
+    match sampler.join() {
+        Ok(Ok(())) => eprintln!("Sampling host statistics stopped"),
+        Ok(Err(err)) => eprintln!("Sampling host statistics failed: {err}"),
+        Err(_) => eprintln!("Sampling host statistics panicked"),
+    }
+    exit(1);
 }
@@ -0,0 +1,149 @@
+//! API link
+
+use std::process::exit;
+use std::thread;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use wu::crypto::random;
+use wu::net::{ConnBuilder, Connection, DEFAULT_READ_TIMEOUT, HEARTBEAT_INTERVAL};
+use wu::protocol::{Handler, Message, VERSION};
+use wu::{Command, Fail, Result};
+
+/// Delay before the first reconnect attempt
+const BACKOFF_START: Duration = Duration::from_secs(1);
+
+/// Next message to send
+pub enum Outgoing {
+    /// Message is ready
+    Message(Message),
+
+    /// Nothing to send yet
+    Idle,
+
+    /// Nothing left to send
+    Done,
+}
+
+/// Registration at the API
+pub struct Link {
+    addr: String,
+    client_id: String,
+    api_key: String,
+    handler: Handler,
+    name: String,
+    session: u64,
+    max_backoff: Duration,
+}
+
+impl Link {
+    /// Create link from command-line configuration
+    pub fn new(cmd: &Command, handler: Handler, api_key: String, name: String) -> Self {
+        let api_port = cmd.parameter("api-port", 4499u16);
+        let api_addr = cmd.parameter("api-addr", "[::1]".to_string());
+        let client_id = cmd.parameter("client-id", name.clone());
+        let max_backoff = cmd.parameter("max-backoff", 60u64);
+        Self {
+            addr: format!("{api_addr}:{api_port}"),
+            client_id,
+            api_key,
+            handler,
+            name,
+            session: u64::from_be_bytes(random(8).try_into().unwrap()),
+            max_backoff: Duration::from_secs(max_backoff.max(1)),
+        }
+    }
+
+    /// Connect and register under the configured name, exit if the API rejects the schema version
+    pub fn connect(&self) -> Result<Connection> {
+        // connect
+        let mut conn = ConnBuilder::new(&self.addr)?
+            .read_timeout(Some(DEFAULT_READ_TIMEOUT))
+            .init(&self.client_id, &self.api_key)?;
+
+        // register
+        conn.send(&Message::Register {
+            handler: self.handler,
+            name: self.name.clone(),
+            session: self.session,
+        })?;
+        match conn.recv() {
+            Ok(Message::Registered) => Ok(conn),
+            Ok(Message::Rejected { reason }) => {
+                Fail::from(format!("registration rejected: {reason}"))
+            }
+            Ok(Message::Incompatible { version }) => {
+                // reconnecting cannot help
+                eprintln!(
+                    "Registration rejected: API speaks message schema {version}, client speaks {VERSION}, update to the same release"
+                );
+                exit(1);
+            }
+            Ok(msg) => Fail::from(format!("unexpected registration response: {msg:?}")),
+            Err(err) => Fail::from(format!("registration failed: {err}")),
+        }
+    }
+
+    /// Reconnect until registered, doubling the delay after each failure
+    pub fn reconnect(&self) -> Connection {
+        let mut delay = BACKOFF_START;
+        loop {
+            sleep(delay);
+            match self.connect() {
+                Ok(conn) => {
+                    println!("Reconnected to API");
+                    return conn;
+                }
+                Err(err) => eprintln!("Reconnect failed: {err}"),
+            }
+            delay = (delay * 2).min(self.max_backoff);
+        }
+    }
+}
+
+/// Send messages from next and heartbeats, pass received messages to on_message
+///
+/// next is called with the time until the next heartbeat is due. Returns when
+/// next is done, or with an error when the connection fails.
+pub fn forward(
+    conn: Connection,
+    mut next: impl FnMut(Duration) -> Outgoing,
+    mut on_message: impl FnMut(Message) + Send + 'static,
+) -> Result<()> {
+    // read commands and heartbeat answers
+    let (mut reader, mut writer) = conn.split()?;
+    let reading = thread::spawn(move || {
+        loop {
+            match reader.recv() {
+                Ok(msg) => on_message(msg),
+                Err(err) => return err,
+            }
+        }
+    });
+
+    let mut next_heartbeat = Instant::now();
+    loop {
+        // check if reading failed
+        if reading.is_finished() {
+            return match reading.join() {
+                Ok(err) => Fail::from(err),
+                Err(_) => Fail::from("reading thread panicked"),
+            };
+        }
+
+        // get next message or heartbeat
+        let now = Instant::now();
+        let msg = if now >= next_heartbeat {
+            next_heartbeat = now + HEARTBEAT_INTERVAL;
+            Message::Heartbeat
+        } else {
+            match next(next_heartbeat - now) {
+                Outgoing::Message(msg) => msg,
+                Outgoing::Idle => continue,
+                Outgoing::Done => return Ok(()),
+            }
+        };
+
+        // send
+        writer.send(&msg)?;
+    }
+}
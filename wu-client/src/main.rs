@@ -3,17 +3,21 @@
 
 pub mod common;
 
+mod cgroup;
 mod handlers;
+mod link;
+mod process;
+mod spool;
+mod sysinfo;
 mod utils;
 
 use common::*;
+use link::Link;
 use std::env::args;
-use std::net::TcpStream;
 use wu::CliBuilder;
-use wu::crypto::init_aead;
 use wu::crypto::random_an;
 use wu::meta::{init_name, init_version};
-use wu::net::ConnBuilder;
+use wu::protocol::Handler;
 
 fn main() {
     // print version
@@ -31,26 +35,24 @@ fn main() {
     }
 
     // configuration
-    let addr = cmd.parameter("addr", "[::]:0".to_string());
-    let api_port = cmd.parameter("api-port", 4499u16);
-    let api_addr = cmd.parameter("api-addr", "[::1]".to_string());
     let api_key = cmd.parameter("api-key", random_an(32));
     let name = cmd.parameter("name", random_an(12));
-    let htype = cmd.arg(0, "");
-
-    // connect
-    let stream = TcpStream::connect(format!("{api_addr}:{api_port}")).unwrap();
-    let aead = init_aead(api_key).unwrap();
-    let mut conn = ConnBuilder::from(stream, &aead).init().unwrap();
-
-    // init connection
-    conn.write(htype.as_bytes()).unwrap();
-    conn.write(name.as_bytes()).unwrap();
+    let handler = match cmd.arg(0, "") {
+        "add-server" => Handler::AddServer,
+        "send-stats" => Handler::SendStats,
+        _ => return println!("{HELP}"),
+    };
+
+    // connect and register
+    let link = Link::new(&cmd, handler, api_key, name);
+    let conn = match link.connect() {
+        Ok(conn) => conn,
+        Err(err) => return eprintln!("Connection to API failed: {err}"),
+    };
 
     // handle
-    match cmd.arg(0, "") {
-        "add-server" => handlers::add_server(conn, cmd, addr),
-        "send-stats" => handlers::send_stats(conn, cmd),
-        _ => println!("{HELP}"),
+    match handler {
+        Handler::AddServer => handlers::add_server(link, conn, cmd),
+        Handler::SendStats => handlers::send_stats(link, conn, cmd),
     }
 }
@@ -0,0 +1,161 @@
+//! Process tree resource accounting
+
+use std::collections::HashMap;
+use std::fs::{read_dir, read_to_string};
+use std::sync::OnceLock;
+use std::time::Instant;
+use wu::protocol::ProcessStats;
+
+/// Kernel clock ticks per second (USER_HZ) if sysconf fails
+const DEFAULT_CLOCK_TICKS: u64 = 100;
+
+/// Kernel clock ticks per second (USER_HZ), read once
+fn clock_ticks() -> u64 {
+    static CLOCK_TICKS: OnceLock<u64> = OnceLock::new();
+    *CLOCK_TICKS.get_or_init(|| {
+        // SAFETY: sysconf has no preconditions
+        match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+            ticks if ticks > 0 => ticks as u64,
+            _ => DEFAULT_CLOCK_TICKS,
+        }
+    })
+}
+
+/// Counters of a process read from /proc/<pid>
+#[derive(Clone, Copy, Debug, Default)]
+struct Usage {
+    ticks: u64,
+    rss: u64,
+    threads: u64,
+    fds: u64,
+    read: u64,
+    write: u64,
+}
+
+impl Usage {
+    /// Read counters of process, none if it exited
+    fn read(pid: u32) -> Option<Self> {
+        // user and system time after the command name
+        let stat = read_to_string(format!("/proc/{pid}/stat")).ok()?;
+        let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_ascii_whitespace().collect();
+        let ticks = fields.get(11)?.parse::<u64>().ok()? + fields.get(12)?.parse::<u64>().ok()?;
+
+        // resident memory and threads
+        let status = read_to_string(format!("/proc/{pid}/status")).ok()?;
+        let value = |key: &str| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix(key)?.split_ascii_whitespace().next())
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+
+        // storage I/O, only readable by the owner
+        let io = read_to_string(format!("/proc/{pid}/io")).unwrap_or_default();
+        let io_value = |key: &str| {
+            io.lines()
+                .find_map(|line| line.strip_prefix(key))
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+
+        Some(Self {
+            ticks,
+            rss: value("VmRSS:").unwrap_or(0),
+            threads: value("Threads:").unwrap_or(0),
+            fds: read_dir(format!("/proc/{pid}/fd")).map_or(0, |fds| fds.count() as u64),
+            read: io_value("read_bytes:"),
+            write: io_value("write_bytes:"),
+        })
+    }
+}
+
+/// Process and its descendants from the parent ids in /proc
+fn process_tree(root: u32) -> Vec<u32> {
+    // map parents to children
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for entry in read_dir("/proc").into_iter().flatten().flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+            continue;
+        };
+        let ppid = read_to_string(format!("/proc/{pid}/stat"))
+            .ok()
+            .and_then(|stat| {
+                stat.rsplit_once(')')?
+                    .1
+                    .split_ascii_whitespace()
+                    .nth(1)?
+                    .parse()
+                    .ok()
+            });
+        if let Some(ppid) = ppid {
+            children.entry(ppid).or_default().push(pid);
+        }
+    }
+
+    // collect descendants
+    let mut tree = vec![root];
+    let mut index = 0;
+    while let Some(&pid) = tree.get(index) {
+        tree.extend(children.get(&pid).into_iter().flatten());
+        index += 1;
+    }
+    tree
+}
+
+/// Samples resource usage of a process tree
+#[derive(Debug)]
+pub struct ProcessSampler {
+    pid: u32,
+    prev: Option<(Usage, Instant)>,
+}
+
+impl ProcessSampler {
+    /// Create sampler for process and its children
+    pub fn new(pid: u32) -> Self {
+        Self { pid, prev: None }
+    }
+
+    /// Sum usage of the process tree, rates are zero on the first sample
+    pub fn sample(&mut self) -> ProcessStats {
+        // sum counters of all processes
+        let tree = process_tree(self.pid);
+        let mut total = Usage::default();
+        let mut processes = 0;
+        for usage in tree.iter().filter_map(|&pid| Usage::read(pid)) {
+            total.ticks += usage.ticks;
+            total.rss += usage.rss;
+            total.threads += usage.threads;
+            total.fds += usage.fds;
+            total.read += usage.read;
+            total.write += usage.write;
+            processes += 1;
+        }
+
+        // calculate rates since previous sample
+        let now = Instant::now();
+        let (cpu, read, write) = match self.prev.replace((total, now)) {
+            Some((prev, time)) => {
+                let secs = now.duration_since(time).as_secs_f64().max(0.001);
+                let rate = |prev: u64, now: u64| now.saturating_sub(prev) as f64 / secs;
+                let cpu = rate(prev.ticks, total.ticks) * 100.0 / clock_ticks() as f64;
+                (
+                    (cpu * 100.0).round() / 100.0,
+                    rate(prev.read, total.read) as u64,
+                    rate(prev.write, total.write) as u64,
+                )
+            }
+            None => (0.0, 0, 0),
+        };
+
+        ProcessStats {
+            processes,
+            cpu,
+            cpu_time: total.ticks * 1000 / clock_ticks(),
+            rss: total.rss,
+            threads: total.threads,
+            fds: total.fds,
+            read,
+            write,
+        }
+    }
+}
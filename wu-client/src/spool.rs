@@ -0,0 +1,84 @@
+//! Console spool
+
+use crate::link::Outgoing;
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+use wu::protocol::Message;
+
+/// Spool state
+struct State {
+    lines: VecDeque<(u64, String)>,
+    next_seq: u64,
+    closed: bool,
+}
+
+/// Bounded spool of console lines not yet acknowledged by the API
+pub struct Spool {
+    state: Mutex<State>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+impl Spool {
+    /// Create spool keeping at most capacity lines
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(State {
+                lines: VecDeque::new(),
+                next_seq: 1,
+                closed: false,
+            }),
+            ready: Condvar::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Append line, dropping the oldest line when full
+    pub fn push(&self, line: String) {
+        let mut state = self.state.lock().unwrap();
+        if state.lines.len() >= self.capacity {
+            state.lines.pop_front();
+        }
+        let seq = state.next_seq;
+        state.lines.push_back((seq, line));
+        state.next_seq += 1;
+        self.ready.notify_all();
+    }
+
+    /// Mark console output as ended
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_all();
+    }
+
+    /// Drop lines the API acknowledged
+    pub fn ack(&self, seq: u64) {
+        let mut state = self.state.lock().unwrap();
+        while state.lines.front().is_some_and(|(s, _)| *s <= seq) {
+            state.lines.pop_front();
+        }
+    }
+
+    /// Wait up to timeout for the first line after seq
+    pub fn next_after(&self, seq: u64, timeout: Duration) -> Outgoing {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self
+            .ready
+            .wait_timeout_while(state, timeout, |state| {
+                !state.closed && state.lines.back().is_none_or(|(s, _)| *s <= seq)
+            })
+            .unwrap();
+
+        // get line or report end of output
+        let pos = state.lines.partition_point(|(s, _)| *s <= seq);
+        match state.lines.get(pos) {
+            Some((seq, line)) => Outgoing::Message(Message::Console {
+                seq: *seq,
+                line: line.clone(),
+            }),
+            None if state.closed => Outgoing::Done,
+            None => Outgoing::Idle,
+        }
+    }
+}
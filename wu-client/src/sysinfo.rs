@@ -0,0 +1,240 @@
+//! Host memory and filesystem information
+
+use std::ffi::CString;
+use std::fs::read_to_string;
+use std::mem::MaybeUninit;
+use wu::{Fail, Result};
+
+/// Filesystem types without disk space worth reporting
+const PSEUDO_FILESYSTEMS: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "proc",
+    "pstore",
+    "ramfs",
+    "rpc_pipefs",
+    "securityfs",
+    "selinuxfs",
+    "squashfs",
+    "sysfs",
+    "tmpfs",
+    "tracefs",
+];
+
+/// Whether the filesystem type is a network or userspace filesystem, statvfs may hang on these
+fn remote(fs: &str) -> bool {
+    fs.starts_with("nfs") || fs.starts_with("fuse.") || matches!(fs, "cifs" | "smb3" | "smbfs")
+}
+
+/// Memory information from /proc/meminfo in kB
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MemInfo {
+    /// Total usable memory
+    pub total: u64,
+
+    /// Memory available for new processes without swapping
+    pub available: u64,
+
+    /// Total swap space
+    pub swap_total: u64,
+
+    /// Unused swap space
+    pub swap_free: u64,
+}
+
+impl MemInfo {
+    /// Read /proc/meminfo
+    pub fn read() -> Result<Self> {
+        Self::parse(&read_to_string("/proc/meminfo").or_else(Fail::from)?)
+    }
+
+    /// Parse meminfo by key, estimating available memory on kernels before 3.14
+    pub fn parse(buf: &str) -> Result<Self> {
+        // find value of key in kB
+        let value = |key: &str| {
+            buf.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                match name.trim() == key {
+                    true => value.split_ascii_whitespace().next()?.parse::<u64>().ok(),
+                    false => None,
+                }
+            })
+        };
+
+        // total memory is required
+        let total = value("MemTotal").ok_or_else(|| Fail::new("MemTotal missing in meminfo"))?;
+        let available = match value("MemAvailable") {
+            Some(available) => available,
+            None => {
+                let reclaimable = ["MemFree", "Buffers", "Cached", "SReclaimable"];
+                reclaimable.iter().filter_map(|key| value(key)).sum()
+            }
+        };
+
+        // swap is missing without swap support
+        Ok(Self {
+            total,
+            available: available.min(total),
+            swap_total: value("SwapTotal").unwrap_or(0),
+            swap_free: value("SwapFree").unwrap_or(0),
+        })
+    }
+
+    /// Memory used and total
+    pub fn mem(&self) -> (u64, u64) {
+        (self.total - self.available, self.total)
+    }
+
+    /// Swap used and total
+    pub fn swap(&self) -> (u64, u64) {
+        (
+            self.swap_total.saturating_sub(self.swap_free),
+            self.swap_total,
+        )
+    }
+}
+
+/// Decode octal escapes like \040 used for spaces in mount paths
+fn unescape(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 4).and_then(|octal| {
+            let octal = std::str::from_utf8(octal).ok()?;
+            u8::from_str_radix(octal, 8).ok()
+        });
+        match (bytes[i], escaped) {
+            (b'\\', Some(byte)) => {
+                out.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Read mount points of /proc/self/mounts
+pub fn mounts() -> Result<Vec<String>> {
+    Ok(parse_mounts(
+        &read_to_string("/proc/self/mounts").or_else(Fail::from)?,
+    ))
+}
+
+/// Mount points of local filesystems, once per device, overlays only as root
+pub fn parse_mounts(buf: &str) -> Vec<String> {
+    let mut devices = Vec::new();
+    let mut paths = Vec::new();
+    for line in buf.lines() {
+        // device, mount point and filesystem type
+        let mut split = line.split_ascii_whitespace();
+        let (Some(device), Some(path), Some(fs)) = (split.next(), split.next(), split.next())
+        else {
+            continue;
+        };
+        let path = unescape(path);
+
+        // skip pseudo and remote filesystems, container overlays and repeated devices
+        if PSEUDO_FILESYSTEMS.contains(&fs)
+            || remote(fs)
+            || (fs == "overlay" && path != "/")
+            || devices.contains(&device)
+            || paths.contains(&path)
+        {
+            continue;
+        }
+        devices.push(device);
+        paths.push(path);
+    }
+    paths
+}
+
+/// Disk space used and total of the filesystem at path in kB
+pub fn disk_usage(path: &str) -> Result<(u64, u64)> {
+    // query filesystem
+    let c_path = CString::new(path).or_else(Fail::from)?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: c_path is a valid C string and stat is written by statvfs on success
+    if unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Fail::from(std::io::Error::last_os_error());
+    }
+    // SAFETY: statvfs succeeded and initialized stat
+    let stat = unsafe { stat.assume_init() };
+
+    // calculate used and total in kB, fields are narrower on 32-bit targets
+    #[allow(clippy::useless_conversion)]
+    let (block, blocks, free) = (
+        u64::from(stat.f_frsize),
+        u64::from(stat.f_blocks),
+        u64::from(stat.f_bfree),
+    );
+    let (total, free) = (blocks * block / 1024, free * block / 1024);
+    Ok((total.saturating_sub(free), total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meminfo_uses_available() {
+        let info = MemInfo::parse(include_str!("../tests/fixtures/meminfo-modern.txt")).unwrap();
+        assert_eq!(info.mem(), (6158152 - 5544768, 6158152));
+        assert_eq!(info.swap(), (2097148 - 1572860, 2097148));
+    }
+
+    #[test]
+    fn meminfo_estimates_available_on_old_kernels() {
+        let info = MemInfo::parse(include_str!("../tests/fixtures/meminfo-legacy.txt")).unwrap();
+        let available = 120412 + 50120 + 402300 + 40080;
+        assert_eq!(info.mem(), (1017464 - available, 1017464));
+        assert_eq!(info.swap(), (0, 0));
+    }
+
+    #[test]
+    fn meminfo_clamps_container_values() {
+        let info = MemInfo::parse(include_str!("../tests/fixtures/meminfo-container.txt")).unwrap();
+        assert_eq!(info.mem(), (0, 2097152));
+        assert_eq!(info.swap(), (0, 0));
+        assert!(MemInfo::parse("MemFree: 1 kB\n").is_err());
+    }
+
+    #[test]
+    fn host_mounts_filtered() {
+        assert_eq!(
+            parse_mounts(include_str!("../tests/fixtures/mounts-host.txt")),
+            ["/", "/boot/efi", "/srv/game servers", "/tank/data"]
+        );
+    }
+
+    #[test]
+    fn container_mounts_filtered() {
+        assert_eq!(
+            parse_mounts(include_str!("../tests/fixtures/mounts-container.txt")),
+            ["/", "/data"]
+        );
+    }
+
+    #[test]
+    fn root_disk_usage() {
+        let (used, total) = disk_usage("/").unwrap();
+        assert!(total > 0 && used <= total);
+        assert!(disk_usage("/nonexistent/mount").is_err());
+    }
+}
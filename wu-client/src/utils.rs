@@ -1,48 +1,172 @@
 //! Client utils
 
+use crate::cgroup::{Cgroup, host_pressure};
+use crate::sysinfo::{MemInfo, disk_usage, mounts};
 use std::fs::OpenOptions;
 use std::io::prelude::*;
-use std::process::Command;
+use std::path::Path;
 use std::result::Result as StdResult;
 use std::sync::mpsc::{Receiver, channel};
 use std::thread::{JoinHandle, sleep, spawn};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use wu::protocol::{CgroupStats, DiskIo, HostStats, MountStats, NetStats, PressureStats};
 use wu::{Fail, Result};
 
-/// Resource usage receiver and thread
-type ResourceUsageRx = (
-    Receiver<(f64, (u64, u64), (u64, u64))>,
-    JoinHandle<StdResult<(), Fail>>,
-);
+/// Host statistics receiver and thread
+type HostStatsRx = (Receiver<HostStats>, JoinHandle<StdResult<(), Fail>>);
 
-/// Get CPU and memory usage every duration except first
-pub fn cpu_mem_usage(duration: Duration) -> ResourceUsageRx {
+/// Cumulative counters to calculate usage and rates between samples
+struct Counters {
+    /// CPU idle and total time, all cores first
+    cpu: Vec<(u64, u64)>,
+
+    /// Received and transmitted bytes per network interface
+    net: Vec<(String, u64, u64)>,
+
+    /// Read and written bytes per block device
+    io: Vec<(String, u64, u64)>,
+
+    /// CPU time used by the control group in microseconds
+    cgroup_cpu: Option<u64>,
+
+    /// Time the counters were read
+    time: Instant,
+}
+
+impl Counters {
+    /// Read current counters, unreadable ones are empty
+    fn read(cgroup: Option<&Cgroup>) -> Self {
+        Self {
+            cpu: sampled("CPU times", read_cpu_times()),
+            net: sampled("network counters", read_net_bytes()),
+            io: sampled("disk counters", read_disk_bytes()),
+            cgroup_cpu: cgroup.and_then(Cgroup::cpu_usage),
+            time: Instant::now(),
+        }
+    }
+}
+
+/// Sampled value or default after logging the error
+fn sampled<T: Default>(what: &str, value: Result<T>) -> T {
+    value.unwrap_or_else(|err| {
+        eprintln!("Failed to read {what}: {err}");
+        T::default()
+    })
+}
+
+/// Control group limits if CPU or memory is limited
+fn read_cgroup(cgroup: &Cgroup) -> Option<CgroupStats> {
+    let cpu_quota = cgroup.cpu_quota();
+    let mem_limit = cgroup.memory().and_then(|(_, limit)| limit);
+    (cpu_quota.is_some() || mem_limit.is_some()).then(|| CgroupStats {
+        version: cgroup.version(),
+        cpu_quota,
+        mem_limit: mem_limit.map(|limit| limit / 1024),
+        pids: cgroup.pids(),
+    })
+}
+
+/// Pressure of the control group or the whole host
+fn read_pressure(cgroup: Option<&Cgroup>) -> Vec<PressureStats> {
+    ["cpu", "memory", "io"]
+        .into_iter()
+        .filter_map(|resource| {
+            let pressure = cgroup
+                .and_then(|cgroup| cgroup.pressure(resource))
+                .or_else(|| host_pressure(resource))?;
+            Some(PressureStats {
+                resource: resource.to_string(),
+                some: pressure.some,
+                full: pressure.full,
+            })
+        })
+        .collect()
+}
+
+/// Get host statistics every duration except first, reporting all mounts if none given
+pub fn host_stats(duration: Duration, mount_paths: Vec<String>) -> HostStatsRx {
     // create channel
     let (tx, rc) = channel();
 
     // spawn sender thread
     let thread = spawn(move || {
-        // read first cpu times and wait first second
-        let (mut prev_idle, mut prev_total) = read_cpu_times().or_else(Fail::std)?;
+        // detect control group, read first counters and wait first interval
+        let cgroup = Cgroup::detect();
+        let mut prev = Counters::read(cgroup.as_ref());
         sleep(duration);
 
-        // send cpu usage continously
+        // send statistics continously
         loop {
-            // read cpu, memory and disk space and calculate difference for cpu
-            let (idle_cpu, total_cpu) = read_cpu_times().or_else(Fail::std)?;
-            let (used_mem, total_mem) = read_memory_usage().or_else(Fail::std)?;
-            let (used_disk, total_disk) = read_disk_space().or_else(Fail::std)?;
-            let dif_idle = (idle_cpu - prev_idle) as f64;
-            let dif_total = (total_cpu - prev_total) as f64;
-
-            // calculate cpu and memory usage and send
-            let cpu_usage = ((1.0 - dif_idle / dif_total) * 10000.0).round() / 100.0;
-            tx.send((cpu_usage, (used_mem, total_mem), (used_disk, total_disk)))
-                .or_else(Fail::std)?;
-
-            // set previous times and wait next second
-            prev_idle = idle_cpu;
-            prev_total = total_cpu;
+            // read counters and calculate usage and rates
+            let now = Counters::read(cgroup.as_ref());
+            let secs = now.time.duration_since(prev.time).as_secs_f64();
+            let mut cores: Vec<f64> = prev
+                .cpu
+                .iter()
+                .zip(&now.cpu)
+                .map(|(&prev, &now)| cpu_usage(prev, now))
+                .collect();
+            let mut cpu = if cores.is_empty() {
+                0.0
+            } else {
+                cores.remove(0)
+            };
+            let net = rates(&prev.net, &now.net, secs)
+                .map(|(interface, rx, tx)| NetStats { interface, rx, tx })
+                .collect();
+            let io = rates(&prev.io, &now.io, secs)
+                .map(|(device, read, write)| DiskIo {
+                    device,
+                    read,
+                    write,
+                })
+                .collect();
+
+            // read current values, skipping failed ones
+            let meminfo = sampled("memory", MemInfo::read());
+            let mut mem = meminfo.mem();
+            let mounts = read_mounts(&mount_paths);
+
+            // prefer control group limits over host values
+            let cgroup_stats = cgroup.as_ref().and_then(read_cgroup);
+            let limits = cgroup_stats.as_ref();
+            if let (Some(quota), Some(prev_usage), Some(usage)) = (
+                limits.and_then(|limits| limits.cpu_quota),
+                prev.cgroup_cpu,
+                now.cgroup_cpu,
+            ) {
+                let used = usage.saturating_sub(prev_usage) as f64 / 1_000_000.0;
+                cpu = (used / (secs * quota) * 10000.0).round().min(10000.0) / 100.0;
+            }
+            if let Some(limit) = limits.and_then(|limits| limits.mem_limit) {
+                let used = cgroup
+                    .as_ref()
+                    .and_then(Cgroup::memory)
+                    .map(|(used, _)| used);
+                mem = (used.unwrap_or(0) / 1024, limit.min(mem.1));
+            }
+            let disk = match mounts.iter().find(|mount| mount.path == "/") {
+                Some(root) => (root.used, root.total),
+                None => sampled("root disk usage", disk_usage("/")),
+            };
+            let stats = HostStats {
+                cpu,
+                mem,
+                disk,
+                load: sampled("load", read_load()),
+                cores,
+                swap: meminfo.swap(),
+                uptime: sampled("uptime", read_uptime()),
+                net,
+                io,
+                mounts,
+                cgroup: cgroup_stats,
+                pressure: read_pressure(cgroup.as_ref()),
+            };
+            tx.send(stats).or_else(Fail::std)?;
+
+            // set previous counters and wait next interval
+            prev = now;
             sleep(duration);
         }
     });
@@ -51,115 +175,174 @@ pub fn cpu_mem_usage(duration: Duration) -> ResourceUsageRx {
     (rc, thread)
 }
 
-/// Get CPU idle and total time from /proc/stat
-fn read_cpu_times() -> Result<(u64, u64)> {
+/// CPU usage in percent between two idle and total times
+fn cpu_usage((prev_idle, prev_total): (u64, u64), (idle, total): (u64, u64)) -> f64 {
+    let dif_idle = idle.saturating_sub(prev_idle) as f64;
+    let dif_total = total.saturating_sub(prev_total) as f64;
+    if dif_total == 0.0 {
+        return 0.0;
+    }
+    ((1.0 - dif_idle / dif_total) * 10000.0).round() / 100.0
+}
+
+/// Per second rates of named counter pairs present in both samples
+fn rates<'a>(
+    prev: &'a [(String, u64, u64)],
+    now: &'a [(String, u64, u64)],
+    secs: f64,
+) -> impl Iterator<Item = (String, u64, u64)> + 'a {
+    let rate = move |prev: u64, now: u64| (now.saturating_sub(prev) as f64 / secs) as u64;
+    now.iter().filter_map(move |(name, a, b)| {
+        let (_, prev_a, prev_b) = prev.iter().find(|(prev_name, _, _)| prev_name == name)?;
+        Some((name.clone(), rate(*prev_a, *a), rate(*prev_b, *b)))
+    })
+}
+
+/// Read file to string
+fn read_file(path: &str) -> Result<String> {
     // open file
     let mut file = OpenOptions::new()
         .read(true)
-        .open("/proc/stat")
+        .open(path)
         .or_else(Fail::from)?;
 
     // read file
     let mut buf = String::new();
     file.read_to_string(&mut buf).or_else(Fail::from)?;
+    Ok(buf)
+}
 
-    // only first line
-    buf = {
-        // split lines and get first
-        let line = buf
-            .split('\n')
-            .next()
-            .ok_or_else(|| Fail::new("broken /proc/stat"))?;
-
-        // line should be at least 10 characters
-        if line.len() < 10 {
-            return Fail::from("broken /proc/stat");
-        }
-
-        // cut "cpu  "
-        line[5..line.len()].to_string()
-    };
+/// Get CPU idle and total time of all cores and each core from /proc/stat
+fn read_cpu_times() -> Result<Vec<(u64, u64)>> {
+    let buf = read_file("/proc/stat")?;
+    let mut times = Vec::new();
+    for line in buf.lines().filter(|line| line.starts_with("cpu")) {
+        // split by whitespace, skip "cpuN" and get idle time
+        let split: Vec<&str> = line.split_ascii_whitespace().skip(1).collect();
+        let idle: u64 = split
+            .get(3)
+            .ok_or_else(|| Fail::new("broken /proc/stat"))?
+            .parse()
+            .or_else(Fail::from)?;
 
-    // split by whitespace and get idle time
-    let split: Vec<&str> = buf.split_ascii_whitespace().collect();
-    let idle: u64 = split[3].parse().or_else(Fail::from)?;
+        // calculate total from all times
+        let mut total = 0u64;
+        for s in split {
+            total += s.parse::<u64>().or_else(Fail::from)?;
+        }
+        times.push((idle, total));
+    }
 
-    // calculate total from all times
-    let mut total = 0u64;
-    for s in split {
-        total += s.parse::<u64>().or_else(Fail::from)?;
+    // return idle and total times
+    match times.is_empty() {
+        true => Fail::from("broken /proc/stat"),
+        false => Ok(times),
     }
+}
 
-    // return idle and total time
-    Ok((idle, total))
+/// Get load averages from /proc/loadavg
+fn read_load() -> Result<(f64, f64, f64)> {
+    let buf = read_file("/proc/loadavg")?;
+    let mut split = buf.split_ascii_whitespace().map(str::parse::<f64>);
+    match (split.next(), split.next(), split.next()) {
+        (Some(Ok(one)), Some(Ok(five)), Some(Ok(fifteen))) => Ok((one, five, fifteen)),
+        _ => Fail::from("broken /proc/loadavg"),
+    }
 }
 
-/// Get memory used and total memory from /proc/meminfo
-fn read_memory_usage() -> Result<(u64, u64)> {
-    // open file
-    let mut file = OpenOptions::new()
-        .read(true)
-        .open("/proc/meminfo")
+/// Get uptime in seconds from /proc/uptime
+fn read_uptime() -> Result<u64> {
+    let buf = read_file("/proc/uptime")?;
+    let uptime: f64 = buf
+        .split_ascii_whitespace()
+        .next()
+        .ok_or_else(|| Fail::new("broken /proc/uptime"))?
+        .parse()
         .or_else(Fail::from)?;
+    Ok(uptime as u64)
+}
 
-    // read file
-    let mut buf = String::new();
-    file.read_to_string(&mut buf).or_else(Fail::from)?;
-
-    // split by whitespace
-    let split: Vec<&str> = buf.split_ascii_whitespace().collect();
-
-    // parse total and used memory
-    let mut total = 0u64;
-    let mut used = 0u64;
-    for s in split {
-        if let Ok(u) = s.parse::<u64>() {
-            if total == 0 {
-                total = u;
-            } else if used == 0 {
-                used = 1;
-            } else {
-                if total < u {
-                    return Fail::from("broken /proc/meminfo");
-                }
-                used = total - u;
-                break;
-            }
+/// Get received and transmitted bytes per interface except loopback from /proc/net/dev
+fn read_net_bytes() -> Result<Vec<(String, u64, u64)>> {
+    let buf = read_file("/proc/net/dev")?;
+    let mut interfaces = Vec::new();
+    for line in buf.lines().skip(2) {
+        // split "interface: rx_bytes ... tx_bytes ..."
+        let (interface, counters) = line
+            .split_once(':')
+            .ok_or_else(|| Fail::new("broken /proc/net/dev"))?;
+        let interface = interface.trim();
+        if interface == "lo" {
+            continue;
         }
+        let counters: Vec<&str> = counters.split_ascii_whitespace().collect();
+        let counter = |index: usize| -> Result<u64> {
+            counters
+                .get(index)
+                .ok_or_else(|| Fail::new("broken /proc/net/dev"))?
+                .parse()
+                .or_else(Fail::from)
+        };
+        interfaces.push((interface.to_string(), counter(0)?, counter(8)?));
     }
-
-    // return used and total memory
-    Ok((used, total))
+    Ok(interfaces)
 }
 
-/// Get used and total disk space from `df /`
-fn read_disk_space() -> Result<(u64, u64)> {
-    // output of `df /`
-    let out = Command::new("df").arg("/").output().or_else(Fail::from)?;
-    let out = String::from_utf8(out.stdout).or_else(Fail::from)?;
-
-    // split by whitespace
-    let split: Vec<&str> = out
-        .lines()
-        .nth(1)
-        .ok_or_else(|| Fail::new("broken `df /` output"))?
-        .split_ascii_whitespace()
-        .collect();
-
-    // get used and total disk space
-    let mut used = 0u64;
-    let mut total = 0u64;
-    for s in split {
-        if let Ok(u) = s.parse::<u64>() {
-            if total == 0 {
-                total = u;
-            } else {
-                used = u;
-                break;
-            }
+/// Get read and written bytes per disk from /proc/diskstats
+fn read_disk_bytes() -> Result<Vec<(String, u64, u64)>> {
+    let buf = read_file("/proc/diskstats")?;
+    let mut devices = Vec::new();
+    for line in buf.lines() {
+        // only whole disks, no partitions or virtual devices
+        let split: Vec<&str> = line.split_ascii_whitespace().collect();
+        let Some(&device) = split.get(2) else {
+            continue;
+        };
+        if device.starts_with("loop")
+            || device.starts_with("ram")
+            || !Path::new("/sys/block").join(device).exists()
+        {
+            continue;
         }
+
+        // sectors read and written are 512 bytes each
+        let sectors = |index: usize| -> Result<u64> {
+            split
+                .get(index)
+                .ok_or_else(|| Fail::new("broken /proc/diskstats"))?
+                .parse::<u64>()
+                .or_else(Fail::from)
+        };
+        devices.push((device.to_string(), sectors(5)? * 512, sectors(9)? * 512));
     }
+    Ok(devices)
+}
 
-    // return used and total disk space
-    Ok((used, total))
+/// Get used and total space of configured mount points or every mounted filesystem
+fn read_mounts(paths: &[String]) -> Vec<MountStats> {
+    // configured or all mount points
+    let all = match paths.is_empty() {
+        true => Some(sampled("mount points", mounts())),
+        false => None,
+    };
+    let paths = all.as_deref().unwrap_or(paths);
+
+    // query filesystems, skipping inaccessible ones and logging configured ones
+    let mut stats = Vec::with_capacity(paths.len());
+    for path in paths {
+        let (used, total) = match disk_usage(path) {
+            Ok(usage) => usage,
+            Err(_) if all.is_some() => continue,
+            Err(err) => {
+                eprintln!("Failed to read disk usage of {path}: {err}");
+                continue;
+            }
+        };
+        stats.push(MountStats {
+            path: path.clone(),
+            used,
+            total,
+        });
+    }
+    stats
 }
@@ -14,13 +14,6 @@ pub fn hash_password(password: &str, username: &str) -> String {
     crypto::hash_password(password, username)
 }
 
-#[wasm_bindgen]
-pub fn argon2_hash(password: &str, username: &str) -> String {
-    let salt = crypto::random(16);
-    let password_hash = crypto::hash_password(password, username);
-    crypto::argon2_hash(password_hash, salt).unwrap()
-}
-
 #[wasm_bindgen]
 pub fn str_encode(data: &str) -> String {
     crypto::hex_encode(data)
@@ -1,6 +1,6 @@
 //! AES256-GCM encryption/decryption
 
-use crate::{Fail, Result};
+use crate::Result;
 pub use aes_gcm::Aes256Gcm;
 use aes_gcm::aead::array::{
     Array,
@@ -8,6 +8,10 @@ use aes_gcm::aead::array::{
     typenum::uint::{UInt, UTerm},
 };
 use aes_gcm::aead::{Aead, KeyInit};
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::result::Result as StdResult;
 
 /// AES 256-bit key
 pub type Aes256Key = Array<u8, UInt<UInt<UInt<UInt<UInt<UInt<UTerm, B1>, B0>, B0>, B0>, B0>, B0>>;
@@ -15,43 +19,168 @@ pub type Aes256Key = Array<u8, UInt<UInt<UInt<UInt<UInt<UInt<UTerm, B1>, B0>, B0
 /// AES 12-byte nonce
 pub type AesNonce = Array<u8, UInt<UInt<UInt<UInt<UTerm, B1>, B1>, B0>, B0>>;
 
+/// Length of the message counter prepended to every encrypted message
+pub const COUNTER_LEN: usize = 8;
+
+/// Length of the authentication tag appended to every encrypted message
+pub const TAG_LEN: usize = 16;
+
 /// Intialize Aes256Gcm with key
 pub fn init_aead(key: impl AsRef<[u8]>) -> Result<Aes256Gcm> {
     let key = key.as_ref().try_into()?;
     Ok(Aes256Gcm::new(&key))
 }
 
+/// Encryption/decryption error
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CryptError {
+    /// Message could not be encrypted
+    Encryption,
+
+    /// Message could not be authenticated or is malformed
+    Authentication,
+
+    /// Message counter does not match the next expected one
+    Replay { expected: u64, got: u64 },
+
+    /// Message counter is exhausted, connection must be re-established
+    Exhausted,
+}
+
+impl Display for CryptError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Encryption => write!(f, "failed to encrypt"),
+            Self::Authentication => write!(f, "failed to decrypt: authentication failed"),
+            Self::Replay { expected, got } => write!(
+                f,
+                "failed to decrypt: replayed or out-of-order message (expected {expected}, got {got})"
+            ),
+            Self::Exhausted => write!(
+                f,
+                "message counter exhausted, connection must be re-established"
+            ),
+        }
+    }
+}
+
+impl Error for CryptError {}
+
 /// AES256-GCM encryption/decryption
-pub struct Crypter<'a> {
-    aead: &'a Aes256Gcm,
-    nonce: AesNonce,
+///
+/// Every message is sealed under its own nonce, derived from a per-direction
+/// message counter. Sending and receiving use separate keys, so counters never
+/// collide. The counter is prepended to the ciphertext, so the receiver can
+/// reject replayed or reordered messages before decrypting them.
+pub struct Crypter {
+    encrypter: Encrypter,
+    decrypter: Decrypter,
 }
 
-impl<'a> Crypter<'a> {
-    /// Create new crypter from existing aead
-    pub fn new(aead: &'a Aes256Gcm, nonce: impl AsRef<[u8]>) -> Result<Self> {
+impl Crypter {
+    /// Create new crypter from sending and receiving keys
+    pub fn new(send_key: impl AsRef<[u8]>, recv_key: impl AsRef<[u8]>) -> Result<Self> {
         Ok(Self {
-            aead,
-            nonce: nonce.as_ref().try_into()?,
+            encrypter: Encrypter {
+                aead: init_aead(send_key)?,
+                counter: 0,
+            },
+            decrypter: Decrypter {
+                aead: init_aead(recv_key)?,
+                counter: 0,
+            },
         })
     }
 
-    /// Encrypt data
-    pub fn encrypt(&self, data: impl AsRef<[u8]>) -> Result<Vec<u8>> {
-        self.aead
-            .encrypt(&self.nonce, data.as_ref())
-            .or_else(|err| Fail::from(format!("failed to encrypt: {err:?}")))
+    /// Encrypt data and prepend message counter
+    pub fn encrypt(&mut self, data: impl AsRef<[u8]>) -> StdResult<Vec<u8>, CryptError> {
+        self.encrypter.encrypt(data)
+    }
+
+    /// Check message counter and decrypt data
+    pub fn decrypt(&mut self, data: impl AsRef<[u8]>) -> StdResult<Vec<u8>, CryptError> {
+        self.decrypter.decrypt(data)
+    }
+
+    /// Split into sending and receiving half
+    pub fn split(self) -> (Encrypter, Decrypter) {
+        (self.encrypter, self.decrypter)
     }
+}
+
+/// Sending half of a crypter
+pub struct Encrypter {
+    aead: Aes256Gcm,
+    counter: u64,
+}
+
+impl Encrypter {
+    /// Encrypt data and prepend message counter
+    pub fn encrypt(&mut self, data: impl AsRef<[u8]>) -> StdResult<Vec<u8>, CryptError> {
+        // next counter or fail
+        let counter = next_counter(&mut self.counter)?;
 
-    /// Decrypt data
-    pub fn decrypt(&self, data: impl AsRef<[u8]>) -> Result<Vec<u8>> {
-        self.aead
-            .decrypt(&self.nonce, data.as_ref())
-            .or_else(|err| Fail::from(format!("failed to decrypt: {err:?}")))
+        // encrypt
+        let enc = self
+            .aead
+            .encrypt(&message_nonce(counter), data.as_ref())
+            .or(Err(CryptError::Encryption))?;
+
+        // prepend counter and return
+        let mut buf = Vec::with_capacity(COUNTER_LEN + enc.len());
+        buf.extend_from_slice(&counter.to_be_bytes());
+        buf.extend_from_slice(&enc);
+        Ok(buf)
     }
+}
+
+/// Receiving half of a crypter
+pub struct Decrypter {
+    aead: Aes256Gcm,
+    counter: u64,
+}
 
-    /// Get AEAD
-    pub fn aead(&self) -> &Aes256Gcm {
-        self.aead
+impl Decrypter {
+    /// Check message counter and decrypt data
+    pub fn decrypt(&mut self, data: impl AsRef<[u8]>) -> StdResult<Vec<u8>, CryptError> {
+        // split counter and encrypted data
+        let data = data.as_ref();
+        if data.len() < COUNTER_LEN {
+            return Err(CryptError::Authentication);
+        }
+        let (counter, enc) = data.split_at(COUNTER_LEN);
+        let counter = u64::from_be_bytes(counter.try_into().or(Err(CryptError::Authentication))?);
+
+        // only accept the next expected message
+        if counter != self.counter {
+            return Err(CryptError::Replay {
+                expected: self.counter,
+                got: counter,
+            });
+        }
+
+        // decrypt
+        let dec = self
+            .aead
+            .decrypt(&message_nonce(counter), enc)
+            .or(Err(CryptError::Authentication))?;
+
+        // only advance counter after successful authentication
+        next_counter(&mut self.counter)?;
+        Ok(dec)
     }
 }
+
+/// Nonce for a single message: zero padded counter
+fn message_nonce(counter: u64) -> AesNonce {
+    let mut nonce = AesNonce::default();
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+/// Return current counter and advance it, failing loudly on exhaustion
+fn next_counter(counter: &mut u64) -> StdResult<u64, CryptError> {
+    let current = *counter;
+    *counter = current.checked_add(1).ok_or(CryptError::Exhausted)?;
+    Ok(current)
+}
@@ -3,10 +3,76 @@
 use argon2::{Config, Variant, hash_encoded, verify_encoded};
 use kern::{Fail, Result};
 
+/// Argon2id cost parameters
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Argon2Params {
+    /// Memory in KiB
+    pub memory: u32,
+
+    /// Number of passes
+    pub iterations: u32,
+
+    /// Degree of parallelism
+    pub lanes: u32,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Self {
+        let config = Config::default();
+        Self {
+            memory: config.mem_cost,
+            iterations: config.time_cost,
+            lanes: config.lanes,
+        }
+    }
+}
+
+impl Argon2Params {
+    /// Read parameters of encoded hash, none if not Argon2id
+    pub fn of(encoded: impl AsRef<str>) -> Option<Self> {
+        // $argon2id$v=19$m=19456,t=2,p=1$salt$hash
+        let mut parts = encoded.as_ref().split('$').skip(1);
+        if parts.next()? != "argon2id" {
+            return None;
+        }
+        let (mut memory, mut iterations, mut lanes) = (None, None, None);
+        for param in parts.nth(1)?.split(',') {
+            match param.split_once('=')? {
+                ("m", value) => memory = value.parse().ok(),
+                ("t", value) => iterations = value.parse().ok(),
+                ("p", value) => lanes = value.parse().ok(),
+                _ => return None,
+            }
+        }
+        Some(Self {
+            memory: memory?,
+            iterations: iterations?,
+            lanes: lanes?,
+        })
+    }
+
+    /// Check if encoded hash was not generated with these parameters
+    pub fn outdated(&self, encoded: impl AsRef<str>) -> bool {
+        Self::of(encoded).as_ref() != Some(self)
+    }
+}
+
 /// Generate Argon2 password hash
 pub fn argon2_hash(pwd: impl AsRef<[u8]>, salt: impl AsRef<[u8]>) -> Result<String> {
+    argon2_hash_with(pwd, salt, &Argon2Params::default())
+}
+
+/// Generate Argon2id password hash with cost parameters
+pub fn argon2_hash_with(
+    pwd: impl AsRef<[u8]>,
+    salt: impl AsRef<[u8]>,
+    params: &Argon2Params,
+) -> Result<String> {
     let config = Config {
         variant: Variant::Argon2id,
+        mem_cost: params.memory,
+        time_cost: params.iterations,
+        lanes: params.lanes,
         ..Default::default()
     };
     hash_encoded(pwd.as_ref(), salt.as_ref(), &config).or_else(Fail::from)
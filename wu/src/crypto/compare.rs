@@ -0,0 +1,6 @@
+//! Constant time comparison
+
+/// Compare without returning early on the first difference
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
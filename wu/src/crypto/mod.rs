@@ -1,10 +1,12 @@
 //! Cryptography utils
 
 mod argon2;
+mod compare;
 mod rand;
 mod sha;
 
 pub use self::argon2::*;
+pub use self::compare::*;
 pub use self::rand::*;
 pub use sha::*;
 
@@ -4,8 +4,11 @@ pub mod crypto;
 
 pub use kern::*;
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", any(feature = "blocking", feature = "async")))]
 pub mod net;
 
+#[cfg(target_os = "linux")]
+pub mod protocol;
+
 #[cfg(target_arch = "wasm32")]
 pub use wasm_bindgen;
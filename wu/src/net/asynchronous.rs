@@ -0,0 +1,300 @@
+//! Asynchronous connections
+
+use super::*;
+use crate::crypto::{Crypter, Decrypter, Encrypter};
+use std::result::Result as StdResult;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::time::timeout;
+
+/// Write length-prefixed frame asynchronously
+pub async fn write_frame_async(
+    writer: &mut (impl AsyncWrite + Unpin),
+    data: &[u8],
+    max_len: usize,
+) -> StdResult<(), FrameError> {
+    // check length
+    check_frame_len(data.len() as u64, max_len)?;
+
+    // write length and data
+    writer.write_all(&(data.len() as u64).to_be_bytes()).await?;
+    writer.write_all(data).await?;
+    Ok(())
+}
+
+/// Read length-prefixed frame asynchronously, never allocating more than max_len
+pub async fn read_frame_async(
+    reader: &mut (impl AsyncRead + Unpin),
+    max_len: usize,
+) -> StdResult<Vec<u8>, FrameError> {
+    // read length, distinguishing close between frames from truncation
+    let mut len_buf = [0u8; 8];
+    let mut read = 0;
+    while read < len_buf.len() {
+        match reader.read(&mut len_buf[read..]).await? {
+            0 if read == 0 => return Err(FrameError::Closed),
+            0 => return Err(FrameError::Truncated),
+            n => read += n,
+        }
+    }
+
+    // check length
+    let len = u64::from_be_bytes(len_buf);
+    check_frame_len(len, max_len)?;
+
+    // read data, growing the buffer only as data arrives
+    let mut buf = Vec::with_capacity((len as usize).min(READ_CHUNK));
+    reader.take(len).read_to_end(&mut buf).await?;
+    if (buf.len() as u64) < len {
+        return Err(FrameError::Truncated);
+    }
+    Ok(buf)
+}
+
+/// Read length-prefixed frame, failing if none arrives within read_timeout
+async fn read_frame_timeout(
+    reader: &mut (impl AsyncRead + Unpin),
+    max_len: usize,
+    read_timeout: Option<Duration>,
+) -> StdResult<Vec<u8>, FrameError> {
+    match read_timeout {
+        Some(duration) => timeout(duration, read_frame_async(reader, max_len))
+            .await
+            .unwrap_or(Err(FrameError::TimedOut)),
+        None => read_frame_async(reader, max_len).await,
+    }
+}
+
+/// Run handshake, failing if it does not finish within read_timeout
+async fn handshake<T>(
+    read_timeout: Option<Duration>,
+    handshake: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match read_timeout {
+        Some(duration) => match timeout(duration, handshake).await {
+            Ok(res) => res,
+            Err(_) => Fail::from("handshake timed out"),
+        },
+        None => handshake.await,
+    }
+}
+
+/// Asynchronous connection builder
+pub struct AsyncConnBuilder {
+    stream: TcpStream,
+    max_frame_len: usize,
+    read_timeout: Option<Duration>,
+}
+
+impl AsyncConnBuilder {
+    /// Create new connection builder
+    pub async fn new(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await.or_else(Fail::from)?;
+        Ok(Self::from(stream))
+    }
+
+    /// Create new connection builder from TcpStream
+    pub fn from(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            read_timeout: None,
+        }
+    }
+
+    /// Set maximum size of a single encrypted frame
+    pub fn max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Set time to wait for a frame before reading fails, including the handshake
+    pub fn read_timeout(mut self, read_timeout: Option<Duration>) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    /// Connection initiator, authenticates as id with pre-shared key
+    pub async fn init(self, id: &str, psk: impl AsRef<[u8]>) -> Result<AsyncConnection> {
+        let step = Handshake::init(id, psk.as_ref())?;
+        let read_timeout = self.read_timeout;
+        handshake(read_timeout, self.handshake(step, |_| None)).await
+    }
+
+    /// Connection acceptor, looks up the pre-shared key for the peer id
+    pub async fn accept(
+        self,
+        psk_for: impl FnOnce(&str) -> Option<Vec<u8>>,
+    ) -> Result<AsyncConnection> {
+        let read_timeout = self.read_timeout;
+        handshake(read_timeout, self.handshake(Handshake::accept(), psk_for)).await
+    }
+
+    /// Do the I/O of every handshake step
+    async fn handshake(
+        mut self,
+        mut step: Step,
+        psk_for: impl FnOnce(&str) -> Option<Vec<u8>>,
+    ) -> Result<AsyncConnection> {
+        let mut psk_for = Some(psk_for);
+        let mut lookup = |id: &str| psk_for.take().and_then(|psk_for| psk_for(id));
+        let session = loop {
+            step = match step {
+                Step::Exchange(handshake, data, len) => {
+                    self.stream.write_all(&data).await.or_else(Fail::from)?;
+                    let mut input = vec![0u8; len];
+                    match self.stream.read_exact(&mut input).await {
+                        Ok(_) => handshake.next(&input, &mut lookup)?,
+                        Err(err) => return handshake.read_failed(err),
+                    }
+                }
+                Step::Reject(data, err) => {
+                    self.stream.write_all(&data).await.or_else(Fail::from)?;
+                    return Fail::from(err);
+                }
+                Step::Done(data, session) => {
+                    self.stream.write_all(&data).await.or_else(Fail::from)?;
+                    break session;
+                }
+            }
+        };
+
+        // return connection
+        Ok(AsyncConnection {
+            stream: self.stream,
+            crypt: session.crypt,
+            id: session.id,
+            psk: session.psk,
+            max_frame_len: self.max_frame_len,
+            read_timeout: self.read_timeout,
+        })
+    }
+}
+
+/// Asynchronous encrypted connection
+pub struct AsyncConnection {
+    stream: TcpStream,
+    crypt: Crypter,
+    id: String,
+    psk: Vec<u8>,
+    max_frame_len: usize,
+    read_timeout: Option<Duration>,
+}
+
+impl AsyncConnection {
+    /// Encrypt and write data
+    pub async fn write(&mut self, data: impl AsRef<[u8]>) -> Result<()> {
+        check_data_len(data.as_ref().len(), self.max_frame_len)?;
+        let enc = self.crypt.encrypt(data.as_ref())?;
+        write_frame_async(&mut self.stream, &enc, self.max_frame_len)
+            .await
+            .or_else(Fail::from)
+    }
+
+    /// Read and decrypt data
+    pub async fn read(&mut self) -> StdResult<Vec<u8>, FrameError> {
+        let buf =
+            read_frame_timeout(&mut self.stream, self.max_frame_len, self.read_timeout).await?;
+        Ok(self.crypt.decrypt(buf)?)
+    }
+
+    /// Encode and write message
+    pub async fn send(&mut self, msg: &Message) -> Result<()> {
+        self.write(msg.encode()?).await
+    }
+
+    /// Read and decode message
+    pub async fn recv(&mut self) -> StdResult<Message, FrameError> {
+        decode_message(self.read().await?)
+    }
+
+    /// Split into independently usable reading and writing half
+    pub fn into_split(self) -> (AsyncConnReader, AsyncConnWriter) {
+        let (encrypter, decrypter) = self.crypt.split();
+        let (read_half, write_half) = self.stream.into_split();
+        let reader = AsyncConnReader {
+            stream: read_half,
+            decrypter,
+            max_frame_len: self.max_frame_len,
+            read_timeout: self.read_timeout,
+        };
+        let writer = AsyncConnWriter {
+            stream: write_half,
+            encrypter,
+            max_frame_len: self.max_frame_len,
+        };
+        (reader, writer)
+    }
+
+    /// Get IP address of TcpStream
+    pub fn stream_ip(&self) -> String {
+        self.stream.peer_addr().unwrap().ip().to_string()
+    }
+
+    /// Client id used in the handshake
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Pre-shared key used in the handshake
+    pub fn psk(&self) -> &[u8] {
+        &self.psk
+    }
+
+    /// Set maximum size of a single encrypted frame
+    pub fn set_max_frame_len(&mut self, max_frame_len: usize) {
+        self.max_frame_len = max_frame_len;
+    }
+
+    /// Set time to wait for a frame before reading fails
+    pub fn set_read_timeout(&mut self, read_timeout: Option<Duration>) {
+        self.read_timeout = read_timeout;
+    }
+}
+
+/// Reading half of an asynchronous encrypted connection
+pub struct AsyncConnReader {
+    stream: OwnedReadHalf,
+    decrypter: Decrypter,
+    max_frame_len: usize,
+    read_timeout: Option<Duration>,
+}
+
+impl AsyncConnReader {
+    /// Read and decrypt data
+    pub async fn read(&mut self) -> StdResult<Vec<u8>, FrameError> {
+        let buf =
+            read_frame_timeout(&mut self.stream, self.max_frame_len, self.read_timeout).await?;
+        Ok(self.decrypter.decrypt(buf)?)
+    }
+
+    /// Read and decode message
+    pub async fn recv(&mut self) -> StdResult<Message, FrameError> {
+        decode_message(self.read().await?)
+    }
+}
+
+/// Writing half of an asynchronous encrypted connection
+pub struct AsyncConnWriter {
+    stream: OwnedWriteHalf,
+    encrypter: Encrypter,
+    max_frame_len: usize,
+}
+
+impl AsyncConnWriter {
+    /// Encrypt and write data
+    pub async fn write(&mut self, data: impl AsRef<[u8]>) -> Result<()> {
+        check_data_len(data.as_ref().len(), self.max_frame_len)?;
+        let enc = self.encrypter.encrypt(data.as_ref())?;
+        write_frame_async(&mut self.stream, &enc, self.max_frame_len)
+            .await
+            .or_else(Fail::from)
+    }
+
+    /// Encode and write message
+    pub async fn send(&mut self, msg: &Message) -> Result<()> {
+        self.write(msg.encode()?).await
+    }
+}
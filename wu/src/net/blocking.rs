@@ -0,0 +1,269 @@
+//! Blocking connections
+
+use super::*;
+use crate::crypto::{Crypter, Decrypter, Encrypter};
+use std::io::ErrorKind;
+use std::io::prelude::*;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::result::Result as StdResult;
+use std::time::Duration;
+
+/// Write length-prefixed frame
+pub fn write_frame(
+    writer: &mut impl Write,
+    data: &[u8],
+    max_len: usize,
+) -> StdResult<(), FrameError> {
+    // check length
+    check_frame_len(data.len() as u64, max_len)?;
+
+    // write length and data
+    writer.write_all(&(data.len() as u64).to_be_bytes())?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+/// Read length-prefixed frame, never allocating more than max_len
+pub fn read_frame(reader: &mut impl Read, max_len: usize) -> StdResult<Vec<u8>, FrameError> {
+    // read length, distinguishing close between frames from truncation
+    let mut len_buf = [0u8; 8];
+    let mut read = 0;
+    while read < len_buf.len() {
+        match reader.read(&mut len_buf[read..]) {
+            Ok(0) if read == 0 => return Err(FrameError::Closed),
+            Ok(0) => return Err(FrameError::Truncated),
+            Ok(n) => read += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    // check length
+    let len = u64::from_be_bytes(len_buf);
+    check_frame_len(len, max_len)?;
+
+    // read data, growing the buffer only as data arrives
+    let mut buf = Vec::with_capacity((len as usize).min(READ_CHUNK));
+    reader.take(len).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < len {
+        return Err(FrameError::Truncated);
+    }
+    Ok(buf)
+}
+
+/// Connection builder
+pub struct ConnBuilder {
+    stream: TcpStream,
+    max_frame_len: usize,
+    read_timeout: Option<Duration>,
+}
+
+impl ConnBuilder {
+    /// Create new connection builder
+    pub fn new(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr).or_else(Fail::from)?;
+        Ok(Self::from(stream))
+    }
+
+    /// Create new connection builder from TcpStream
+    pub fn from(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            read_timeout: None,
+        }
+    }
+
+    /// Set maximum size of a single encrypted frame
+    pub fn max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Set time to wait for a frame before reading fails, including the handshake
+    pub fn read_timeout(mut self, read_timeout: Option<Duration>) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    /// Connection initiator, authenticates as id with pre-shared key
+    pub fn init(self, id: &str, psk: impl AsRef<[u8]>) -> Result<Connection> {
+        let step = Handshake::init(id, psk.as_ref())?;
+        self.handshake(step, |_| None)
+    }
+
+    /// Connection acceptor, looks up the pre-shared key for the peer id
+    pub fn accept(self, psk_for: impl FnOnce(&str) -> Option<Vec<u8>>) -> Result<Connection> {
+        self.handshake(Handshake::accept(), psk_for)
+    }
+
+    /// Do the I/O of every handshake step
+    fn handshake(
+        mut self,
+        mut step: Step,
+        psk_for: impl FnOnce(&str) -> Option<Vec<u8>>,
+    ) -> Result<Connection> {
+        self.stream
+            .set_read_timeout(self.read_timeout)
+            .or_else(Fail::from)?;
+        let mut psk_for = Some(psk_for);
+        let mut lookup = |id: &str| psk_for.take().and_then(|psk_for| psk_for(id));
+        let session = loop {
+            step = match step {
+                Step::Exchange(handshake, data, len) => {
+                    self.stream.write_all(&data).or_else(Fail::from)?;
+                    let mut input = vec![0u8; len];
+                    match self.stream.read_exact(&mut input) {
+                        Ok(()) => handshake.next(&input, &mut lookup)?,
+                        Err(err) => return handshake.read_failed(err),
+                    }
+                }
+                Step::Reject(data, err) => {
+                    self.stream.write_all(&data).or_else(Fail::from)?;
+                    return Fail::from(err);
+                }
+                Step::Done(data, session) => {
+                    self.stream.write_all(&data).or_else(Fail::from)?;
+                    break session;
+                }
+            }
+        };
+
+        // return connection
+        Ok(Connection {
+            stream: self.stream,
+            crypt: session.crypt,
+            id: session.id,
+            psk: session.psk,
+            max_frame_len: self.max_frame_len,
+        })
+    }
+}
+
+/// Encrypted connection
+pub struct Connection {
+    stream: TcpStream,
+    crypt: Crypter,
+    id: String,
+    psk: Vec<u8>,
+    max_frame_len: usize,
+}
+
+impl Connection {
+    /// Reinitiate connection
+    pub fn reinit(&self) -> Result<Connection> {
+        let addr = self.stream.peer_addr().or_else(Fail::from)?;
+        let read_timeout = self.stream.read_timeout().or_else(Fail::from)?;
+        ConnBuilder::new(addr)?
+            .max_frame_len(self.max_frame_len)
+            .read_timeout(read_timeout)
+            .init(&self.id, &self.psk)
+    }
+
+    /// Encrypt and write data
+    pub fn write(&mut self, data: impl AsRef<[u8]>) -> Result<()> {
+        check_data_len(data.as_ref().len(), self.max_frame_len)?;
+        let enc = self.crypt.encrypt(data.as_ref())?;
+        write_frame(&mut self.stream, &enc, self.max_frame_len).or_else(Fail::from)
+    }
+
+    /// Read and decrypt data
+    pub fn read(&mut self) -> StdResult<Vec<u8>, FrameError> {
+        let buf = read_frame(&mut self.stream, self.max_frame_len)?;
+        Ok(self.crypt.decrypt(buf)?)
+    }
+
+    /// Encode and write message
+    pub fn send(&mut self, msg: &Message) -> Result<()> {
+        self.write(msg.encode()?)
+    }
+
+    /// Read and decode message
+    pub fn recv(&mut self) -> StdResult<Message, FrameError> {
+        decode_message(self.read()?)
+    }
+
+    /// Split into independently usable reading and writing half
+    pub fn split(self) -> Result<(ConnReader, ConnWriter)> {
+        let (encrypter, decrypter) = self.crypt.split();
+        let reader = ConnReader {
+            stream: self.stream.try_clone().or_else(Fail::from)?,
+            decrypter,
+            max_frame_len: self.max_frame_len,
+        };
+        let writer = ConnWriter {
+            stream: self.stream,
+            encrypter,
+            max_frame_len: self.max_frame_len,
+        };
+        Ok((reader, writer))
+    }
+
+    /// Get IP address of TcpStream
+    pub fn stream_ip(&self) -> String {
+        self.stream.peer_addr().unwrap().ip().to_string()
+    }
+
+    /// Client id used in the handshake
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Pre-shared key used in the handshake
+    pub fn psk(&self) -> &[u8] {
+        &self.psk
+    }
+
+    /// Set maximum size of a single encrypted frame
+    pub fn set_max_frame_len(&mut self, max_frame_len: usize) {
+        self.max_frame_len = max_frame_len;
+    }
+
+    /// Set time to wait for a frame before reading fails, shared with split halves
+    pub fn set_read_timeout(&self, read_timeout: Option<Duration>) -> Result<()> {
+        self.stream
+            .set_read_timeout(read_timeout)
+            .or_else(Fail::from)
+    }
+}
+
+/// Reading half of an encrypted connection
+pub struct ConnReader {
+    stream: TcpStream,
+    decrypter: Decrypter,
+    max_frame_len: usize,
+}
+
+impl ConnReader {
+    /// Read and decrypt data
+    pub fn read(&mut self) -> StdResult<Vec<u8>, FrameError> {
+        let buf = read_frame(&mut self.stream, self.max_frame_len)?;
+        Ok(self.decrypter.decrypt(buf)?)
+    }
+
+    /// Read and decode message
+    pub fn recv(&mut self) -> StdResult<Message, FrameError> {
+        decode_message(self.read()?)
+    }
+}
+
+/// Writing half of an encrypted connection
+pub struct ConnWriter {
+    stream: TcpStream,
+    encrypter: Encrypter,
+    max_frame_len: usize,
+}
+
+impl ConnWriter {
+    /// Encrypt and write data
+    pub fn write(&mut self, data: impl AsRef<[u8]>) -> Result<()> {
+        check_data_len(data.as_ref().len(), self.max_frame_len)?;
+        let enc = self.encrypter.encrypt(data.as_ref())?;
+        write_frame(&mut self.stream, &enc, self.max_frame_len).or_else(Fail::from)
+    }
+
+    /// Encode and write message
+    pub fn send(&mut self, msg: &Message) -> Result<()> {
+        self.write(msg.encode()?)
+    }
+}
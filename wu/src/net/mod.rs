@@ -0,0 +1,432 @@
+//! Network utils
+
+#[cfg(feature = "async")]
+mod asynchronous;
+#[cfg(feature = "blocking")]
+mod blocking;
+
+#[cfg(feature = "async")]
+pub use asynchronous::*;
+#[cfg(feature = "blocking")]
+pub use blocking::*;
+
+use crate::crypto::{COUNTER_LEN, CryptError, Crypter, TAG_LEN, ct_eq, random};
+use crate::protocol::{Message, ProtocolError};
+use hkdf::SimpleHkdf;
+use kern::{Fail, Result};
+use sha3::{Digest, Sha3_256};
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Error as IoError, ErrorKind};
+use std::time::Duration;
+use x25519_dalek::{X25519_BASEPOINT_BYTES, x25519};
+
+/// Handshake magic bytes
+const MAGIC: &[u8; 2] = b"WU";
+
+/// Protocol version spoken by this build
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Default maximum size of a single encrypted frame (1 MiB)
+pub const DEFAULT_MAX_FRAME_LEN: usize = 1 << 20;
+
+/// Interval in which peers send heartbeats
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Default time without any frame after which a peer is considered dead
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Buffer growth step when reading frame data
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Length of the initiator hello head: magic, version and id length
+const HEAD_LEN: usize = 4;
+
+/// Length of the acceptor reply after the status byte
+const REPLY_LEN: usize = 96;
+
+/// Length of the initiator confirmation
+const CONFIRM_LEN: usize = 32;
+
+/// Handshake status: accepted
+const STATUS_OK: u8 = 0;
+
+/// Handshake status: unsupported protocol version
+const STATUS_VERSION: u8 = 1;
+
+/// Framing error
+#[derive(Debug)]
+pub enum FrameError {
+    /// Peer closed the connection between frames
+    Closed,
+
+    /// Connection ended in the middle of a frame
+    Truncated,
+
+    /// Frame length exceeds the maximum frame size
+    Oversized { len: u64, max: usize },
+
+    /// Frame could not be encrypted, authenticated or was replayed
+    Crypt(CryptError),
+
+    /// Message could not be decoded
+    Protocol(ProtocolError),
+
+    /// No frame arrived within the read timeout, the connection should be dropped
+    TimedOut,
+
+    /// Underlying I/O error
+    Io(IoError),
+}
+
+impl Display for FrameError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Closed => write!(f, "connection closed"),
+            Self::Truncated => write!(f, "truncated frame"),
+            Self::Oversized { len, max } => {
+                write!(f, "frame of {len} bytes exceeds maximum of {max} bytes")
+            }
+            Self::Crypt(err) => write!(f, "{err}"),
+            Self::Protocol(err) => write!(f, "{err}"),
+            Self::TimedOut => write!(f, "read timed out"),
+            Self::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for FrameError {}
+
+impl From<CryptError> for FrameError {
+    fn from(err: CryptError) -> Self {
+        Self::Crypt(err)
+    }
+}
+
+impl From<ProtocolError> for FrameError {
+    fn from(err: ProtocolError) -> Self {
+        Self::Protocol(err)
+    }
+}
+
+impl From<IoError> for FrameError {
+    fn from(err: IoError) -> Self {
+        match err.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => Self::TimedOut,
+            _ => Self::Io(err),
+        }
+    }
+}
+
+/// Decode message from decrypted frame
+fn decode_message(buf: Vec<u8>) -> std::result::Result<Message, FrameError> {
+    Ok(Message::decode(&buf)?)
+}
+
+/// Check frame length against maximum
+fn check_frame_len(len: u64, max_len: usize) -> std::result::Result<(), FrameError> {
+    match len > max_len as u64 {
+        true => Err(FrameError::Oversized { len, max: max_len }),
+        false => Ok(()),
+    }
+}
+
+/// Check that data fits into a frame once encrypted, before encrypting uses up a message counter
+fn check_data_len(len: usize, max_len: usize) -> Result<()> {
+    check_frame_len((COUNTER_LEN + len + TAG_LEN) as u64, max_len).or_else(Fail::from)
+}
+
+/// Established handshake
+struct Session {
+    crypt: Crypter,
+    id: String,
+    psk: Vec<u8>,
+}
+
+/// I/O requested by a handshake step
+enum Step {
+    /// Write data, then read exactly this many bytes and pass them to the handshake
+    Exchange(Handshake, Vec<u8>, usize),
+
+    /// Write data, then fail with message
+    Reject(Vec<u8>, String),
+
+    /// Write data, the handshake is finished
+    Done(Vec<u8>, Box<Session>),
+}
+
+/// Handshake state, the connection builders only do the I/O of each step
+enum Handshake {
+    /// Initiator waits for the status
+    Status(Initiator),
+
+    /// Initiator waits for the version of a mismatching acceptor
+    Version,
+
+    /// Initiator waits for the acceptor reply
+    Reply(Initiator),
+
+    /// Acceptor waits for magic, version and id length
+    Head,
+
+    /// Acceptor waits for id, ephemeral key and random
+    Rest([u8; HEAD_LEN]),
+
+    /// Acceptor waits for the initiator confirmation
+    Confirm(Box<Acceptor>),
+}
+
+impl Handshake {
+    /// Start as initiator, authenticating as id with pre-shared key
+    fn init(id: &str, psk: &[u8]) -> Result<Step> {
+        let initiator = Initiator::new(id, psk)?;
+        let hello = initiator.hello.clone();
+        Ok(Step::Exchange(Self::Status(initiator), hello, 1))
+    }
+
+    /// Start as acceptor
+    fn accept() -> Step {
+        Step::Exchange(Self::Head, Vec::new(), HEAD_LEN)
+    }
+
+    /// Advance with the bytes read, psk_for looks up the pre-shared key of the peer id
+    fn next(self, input: &[u8], psk_for: &mut dyn FnMut(&str) -> Option<Vec<u8>>) -> Result<Step> {
+        match self {
+            Self::Status(initiator) => match input[0] {
+                STATUS_OK => Ok(Step::Exchange(
+                    Self::Reply(initiator),
+                    Vec::new(),
+                    REPLY_LEN,
+                )),
+                STATUS_VERSION => Ok(Step::Exchange(Self::Version, Vec::new(), 1)),
+                _ => Fail::from("handshake failed: invalid status"),
+            },
+            Self::Version => Fail::from(format!(
+                "protocol version mismatch: client speaks {PROTOCOL_VERSION}, API speaks {}",
+                input[0]
+            )),
+            Self::Reply(initiator) => {
+                let (confirm, session) = initiator.finish(input.try_into()?)?;
+                Ok(Step::Done(confirm, Box::new(session)))
+            }
+            Self::Head => {
+                let head: [u8; HEAD_LEN] = input.try_into()?;
+                match Acceptor::check_head(&head)? {
+                    Ok(rest_len) => Ok(Step::Exchange(Self::Rest(head), Vec::new(), rest_len)),
+                    Err(reply) => Ok(Step::Reject(
+                        reply.to_vec(),
+                        Acceptor::version_mismatch(&head),
+                    )),
+                }
+            }
+            Self::Rest(head) => {
+                let acceptor = Acceptor::new(head, input.to_vec(), psk_for)?;
+                let reply = acceptor.reply.clone();
+                Ok(Step::Exchange(
+                    Self::Confirm(Box::new(acceptor)),
+                    reply,
+                    CONFIRM_LEN,
+                ))
+            }
+            Self::Confirm(acceptor) => {
+                let session = acceptor.verify(input.try_into()?)?;
+                Ok(Step::Done(Vec::new(), Box::new(session)))
+            }
+        }
+    }
+
+    /// Error for a failed read, a missing confirmation counts as invalid key
+    fn read_failed<T>(self, err: IoError) -> Result<T> {
+        match self {
+            Self::Confirm(acceptor) => acceptor.rejected(),
+            _ => Fail::from(err),
+        }
+    }
+}
+
+/// Initiator side of the handshake
+struct Initiator {
+    secret: [u8; 32],
+    hello: Vec<u8>,
+    id: String,
+    psk: Vec<u8>,
+}
+
+impl Initiator {
+    /// Generate ephemeral key and hello message
+    fn new(id: &str, psk: &[u8]) -> Result<Self> {
+        // check id length
+        let id_len: u8 = id
+            .len()
+            .try_into()
+            .or_else(|_| Fail::from("client id too long"))?;
+
+        // build hello
+        let (secret, public) = ephemeral_key();
+        let mut hello = Vec::with_capacity(HEAD_LEN + id.len() + 64);
+        hello.extend_from_slice(MAGIC);
+        hello.push(PROTOCOL_VERSION);
+        hello.push(id_len);
+        hello.extend_from_slice(id.as_bytes());
+        hello.extend_from_slice(&public);
+        hello.extend_from_slice(&random(32));
+        Ok(Self {
+            secret,
+            hello,
+            id: id.to_string(),
+            psk: psk.to_vec(),
+        })
+    }
+
+    /// Verify acceptor reply, derive keys and build confirmation
+    fn finish(self, reply: &[u8; REPLY_LEN]) -> Result<(Vec<u8>, Session)> {
+        // derive keys
+        let peer_public: [u8; 32] = reply[..32].try_into()?;
+        let transcript = [&self.hello[..], &reply[..64]].concat();
+        let keys = SessionKeys::derive(&self.secret, &peer_public, &self.psk, &transcript)?;
+
+        // verify acceptor
+        if !ct_eq(&reply[64..], &keys.confirm_a) {
+            return Fail::from("handshake failed: acceptor could not be authenticated");
+        }
+        let session = Session {
+            crypt: Crypter::new(keys.key_i, keys.key_a)?,
+            id: self.id,
+            psk: self.psk,
+        };
+        Ok((keys.confirm_i.to_vec(), session))
+    }
+}
+
+/// Acceptor side of the handshake after reading the hello
+struct Acceptor {
+    id: String,
+    psk: Option<Vec<u8>>,
+    keys: SessionKeys,
+    reply: Vec<u8>,
+}
+
+impl Acceptor {
+    /// Check magic and version, return rest length or reply for version mismatch
+    fn check_head(head: &[u8; HEAD_LEN]) -> Result<std::result::Result<usize, [u8; 2]>> {
+        if &head[..2] != MAGIC {
+            return Fail::from(
+                "handshake failed: peer does not speak the handshake (outdated client?)",
+            );
+        }
+        match head[2] == PROTOCOL_VERSION {
+            true => Ok(Ok(head[3] as usize + 64)),
+            false => Ok(Err([STATUS_VERSION, PROTOCOL_VERSION])),
+        }
+    }
+
+    /// Error message for version mismatch
+    fn version_mismatch(head: &[u8; HEAD_LEN]) -> String {
+        format!(
+            "protocol version mismatch: client speaks {}, API speaks {PROTOCOL_VERSION}",
+            head[2]
+        )
+    }
+
+    /// Parse hello, derive keys and build reply
+    ///
+    /// Unknown ids get a random key, so they fail at the confirmation like a
+    /// wrong key instead of revealing which ids exist.
+    fn new(
+        head: [u8; HEAD_LEN],
+        rest: Vec<u8>,
+        psk_for: &mut dyn FnMut(&str) -> Option<Vec<u8>>,
+    ) -> Result<Self> {
+        // id and peer ephemeral key
+        let id_len = head[3] as usize;
+        let id = String::from_utf8(rest[..id_len].to_vec()).or_else(Fail::from)?;
+        let peer_public: [u8; 32] = rest[id_len..id_len + 32].try_into()?;
+        let psk = psk_for(&id);
+
+        // generate ephemeral key and derive keys
+        let (secret, public) = ephemeral_key();
+        let random_a = random(32);
+        let transcript = [&head[..], &rest, &public, &random_a].concat();
+        let key = psk.clone().unwrap_or_else(|| random(32));
+        let keys = SessionKeys::derive(&secret, &peer_public, &key, &transcript)?;
+
+        // status, hello and confirmation
+        let reply = [&[STATUS_OK][..], &public, &random_a, &keys.confirm_a].concat();
+        Ok(Self {
+            id,
+            psk,
+            keys,
+            reply,
+        })
+    }
+
+    /// Verify initiator confirmation
+    fn verify(self, confirm: &[u8; CONFIRM_LEN]) -> Result<Session> {
+        match self.psk {
+            Some(psk) if ct_eq(confirm, &self.keys.confirm_i) => Ok(Session {
+                crypt: Crypter::new(self.keys.key_a, self.keys.key_i)?,
+                id: self.id,
+                psk,
+            }),
+            _ => self.rejected(),
+        }
+    }
+
+    /// Error for missing or wrong confirmation, unknown ids are only told apart here
+    fn rejected<T>(&self) -> Result<T> {
+        match self.psk {
+            Some(_) => Fail::from(format!(
+                "handshake failed: client {} used an invalid key",
+                self.id
+            )),
+            None => Fail::from(format!("handshake rejected: unknown client {}", self.id)),
+        }
+    }
+}
+
+/// Keys derived from the handshake
+struct SessionKeys {
+    key_i: [u8; 32],
+    key_a: [u8; 32],
+    confirm_i: [u8; 32],
+    confirm_a: [u8; 32],
+}
+
+impl SessionKeys {
+    /// Derive session keys from ephemeral Diffie-Hellman, pre-shared key and transcript
+    fn derive(
+        secret: &[u8; 32],
+        peer_public: &[u8; 32],
+        psk: &[u8],
+        transcript: &[u8],
+    ) -> Result<Self> {
+        // shared secret, reject low order points
+        let shared = x25519(*secret, *peer_public);
+        if shared.iter().all(|b| *b == 0) {
+            return Fail::from("handshake failed: invalid public key");
+        }
+
+        // expand keys bound to the transcript
+        let info = Sha3_256::digest(transcript);
+        let hkdf = SimpleHkdf::<Sha3_256>::new(Some(psk), &shared);
+        let mut okm = [0u8; 128];
+        hkdf.expand(&info, &mut okm)
+            .or_else(|_| Fail::from("handshake failed: key derivation"))?;
+
+        // split keys
+        Ok(Self {
+            key_i: okm[..32].try_into()?,
+            key_a: okm[32..64].try_into()?,
+            confirm_i: okm[64..96].try_into()?,
+            confirm_a: okm[96..].try_into()?,
+        })
+    }
+}
+
+/// Generate ephemeral X25519 secret and public key
+fn ephemeral_key() -> ([u8; 32], [u8; 32]) {
+    let mut secret = [0u8; 32];
+    secret.copy_from_slice(&random(32));
+    (secret, x25519(secret, X25519_BASEPOINT_BYTES))
+}
@@ -0,0 +1,268 @@
+//! Client API message schema
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// Message schema version, bumped on incompatible changes only
+pub const VERSION: u16 = 6;
+
+/// Schema revision, bumped when fields are appended to the last struct of a message
+///
+/// Peers of a newer revision send fields that are ignored. The first revision
+/// appending a field must also pad messages of older revisions with zeros before
+/// decoding, so appended fields must default to zero, false, none or empty.
+pub const REVISION: u16 = 0;
+
+/// Handler a client registers for
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Handler {
+    /// Managed server with console
+    AddServer,
+
+    /// Host statistics
+    SendStats,
+}
+
+impl Handler {
+    /// Handler type name as used in the client registry and CLI
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::AddServer => "add-server",
+            Self::SendStats => "send-stats",
+        }
+    }
+}
+
+/// Network interface throughput
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct NetStats {
+    /// Interface name
+    pub interface: String,
+
+    /// Received bytes per second
+    pub rx: u64,
+
+    /// Transmitted bytes per second
+    pub tx: u64,
+}
+
+/// Block device throughput
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct DiskIo {
+    /// Device name
+    pub device: String,
+
+    /// Read bytes per second
+    pub read: u64,
+
+    /// Written bytes per second
+    pub write: u64,
+}
+
+/// Mounted filesystem usage
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct MountStats {
+    /// Mount point
+    pub path: String,
+
+    /// Space used in kB
+    pub used: u64,
+
+    /// Space total in kB
+    pub total: u64,
+}
+
+/// Control group limits of a containerized host
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CgroupStats {
+    /// Hierarchy version, 1 or 2
+    pub version: u8,
+
+    /// CPU quota in cores, none if unlimited
+    pub cpu_quota: Option<f64>,
+
+    /// Memory limit in kB, none if unlimited
+    pub mem_limit: Option<u64>,
+
+    /// Number of tasks and limit, none if unavailable or unlimited
+    pub pids: Option<(u64, Option<u64>)>,
+}
+
+/// Pressure stall information of a resource
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct PressureStats {
+    /// Resource name: cpu, memory or io
+    pub resource: String,
+
+    /// Percentage of time some tasks stalled over the last 10 seconds
+    pub some: f64,
+
+    /// Percentage of time all tasks stalled over the last 10 seconds
+    pub full: f64,
+}
+
+/// Host statistics sample
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct HostStats {
+    /// CPU usage in percent, of the quota if limited by the control group
+    pub cpu: f64,
+
+    /// Memory used and total in kB, total is the control group limit if lower
+    pub mem: (u64, u64),
+
+    /// Disk space of the root filesystem used and total in kB
+    pub disk: (u64, u64),
+
+    /// Load averages over 1, 5 and 15 minutes
+    pub load: (f64, f64, f64),
+
+    /// CPU usage per core in percent
+    pub cores: Vec<f64>,
+
+    /// Swap used and total in kB
+    pub swap: (u64, u64),
+
+    /// Uptime in seconds
+    pub uptime: u64,
+
+    /// Throughput per network interface
+    pub net: Vec<NetStats>,
+
+    /// Throughput per block device
+    pub io: Vec<DiskIo>,
+
+    /// Usage of every mounted filesystem
+    pub mounts: Vec<MountStats>,
+
+    /// Control group limits, none on bare metal
+    pub cgroup: Option<CgroupStats>,
+
+    /// Pressure stall information, empty if unsupported
+    pub pressure: Vec<PressureStats>,
+}
+
+/// Resource usage of a managed server's process tree
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ProcessStats {
+    /// Number of processes in the tree
+    pub processes: u32,
+
+    /// CPU usage in percent of one core
+    pub cpu: f64,
+
+    /// Total CPU time in milliseconds
+    pub cpu_time: u64,
+
+    /// Resident memory in kB
+    pub rss: u64,
+
+    /// Number of threads
+    pub threads: u64,
+
+    /// Number of open file descriptors
+    pub fds: u64,
+
+    /// Read bytes per second
+    pub read: u64,
+
+    /// Written bytes per second
+    pub write: u64,
+}
+
+/// Message exchanged between client and API
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Message {
+    /// Client registers a handler under a name, session is random per client run
+    Register {
+        handler: Handler,
+        name: String,
+        session: u64,
+    },
+
+    /// API accepted the registration
+    Registered,
+
+    /// API rejected the registration
+    Rejected { reason: String },
+
+    /// Console output line from client to API, numbered from 1 per session
+    Console { seq: u64, line: String },
+
+    /// Highest console line received by the API, answers heartbeats
+    Ack(u64),
+
+    /// Console command from API to client
+    Command(String),
+
+    /// Keep-alive in either direction
+    Heartbeat,
+
+    /// Host statistics from client to API
+    Stats(Box<HostStats>),
+
+    /// Managed server process usage from client to API
+    Process(ProcessStats),
+
+    /// API cannot decode the client's schema version, encoded as bare version
+    /// so peers of every version read it
+    Incompatible { version: u16 },
+}
+
+/// Message encoding/decoding error
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ProtocolError {
+    /// Peer uses a different schema version
+    Version { local: u16, remote: u16 },
+
+    /// Message could not be encoded or decoded
+    Malformed(String),
+}
+
+impl Display for ProtocolError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Version { local, remote } => write!(
+                f,
+                "message schema version mismatch: local {local}, remote {remote}"
+            ),
+            Self::Malformed(err) => write!(f, "malformed message: {err}"),
+        }
+    }
+}
+
+impl Error for ProtocolError {}
+
+impl Message {
+    /// Encode message prefixed with schema version and revision
+    pub fn encode(&self) -> Result<Vec<u8>, ProtocolError> {
+        if let Self::Incompatible { version } = self {
+            return Ok(version.to_be_bytes().to_vec());
+        }
+        let mut buf = VERSION.to_be_bytes().to_vec();
+        buf.extend_from_slice(&REVISION.to_be_bytes());
+        postcard::to_io(self, &mut buf).map_err(|err| ProtocolError::Malformed(err.to_string()))?;
+        Ok(buf)
+    }
+
+    /// Check schema version and decode message of any revision
+    pub fn decode(buf: &[u8]) -> Result<Self, ProtocolError> {
+        // check version
+        if buf.len() == 2 {
+            let version = u16::from_be_bytes([buf[0], buf[1]]);
+            return Ok(Self::Incompatible { version });
+        } else if buf.len() < 4 {
+            return Err(ProtocolError::Malformed("missing version".to_string()));
+        }
+        let remote = u16::from_be_bytes([buf[0], buf[1]]);
+        if remote != VERSION {
+            return Err(ProtocolError::Version {
+                local: VERSION,
+                remote,
+            });
+        }
+
+        // decode, trailing fields of newer revisions are ignored
+        postcard::from_bytes(&buf[4..]).map_err(|err| ProtocolError::Malformed(err.to_string()))
+    }
+}
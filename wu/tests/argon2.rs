@@ -0,0 +1,26 @@
+//! Argon2 password hashing tests
+
+use wu::crypto::{Argon2Params, argon2_hash_with, argon2_verify};
+
+#[test]
+fn params_read_from_hash() {
+    let params = Argon2Params {
+        memory: 4096,
+        iterations: 1,
+        lanes: 2,
+    };
+    let encoded = argon2_hash_with("password", [0u8; 16], &params).unwrap();
+    assert!(argon2_verify(&encoded, "password"));
+    assert_eq!(Argon2Params::of(&encoded), Some(params));
+    assert!(!params.outdated(&encoded));
+    assert!(Argon2Params::default().outdated(&encoded));
+}
+
+#[test]
+fn foreign_hashes_outdated() {
+    let params = Argon2Params::default();
+    let argon2i = "$argon2i$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaA";
+    assert_eq!(Argon2Params::of(argon2i), None);
+    assert!(params.outdated(argon2i));
+    assert!(params.outdated("5d41402abc4b2a76b9719d911017c592"));
+}
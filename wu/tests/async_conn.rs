@@ -0,0 +1,130 @@
+//! Asynchronous connection tests
+#![cfg(all(target_os = "linux", feature = "async", feature = "blocking"))]
+
+use std::thread;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use wu::net::{AsyncConnBuilder, ConnBuilder, FrameError};
+use wu::protocol::Message;
+
+/// Console message
+fn console(seq: u64, line: &str) -> Message {
+    let line = line.to_string();
+    Message::Console { seq, line }
+}
+
+#[tokio::test]
+async fn async_acceptor_talks_to_blocking_initiator() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // blocking client echoes one message
+    let client = thread::spawn(move || {
+        let mut conn = ConnBuilder::new(addr)
+            .unwrap()
+            .init("client", b"key")
+            .unwrap();
+        conn.write(b"hello").unwrap();
+        conn.read().unwrap()
+    });
+
+    // async acceptor
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut conn = AsyncConnBuilder::from(stream)
+        .accept(|id| (id == "client").then(|| b"key".to_vec()))
+        .await
+        .unwrap();
+    assert_eq!(conn.id(), "client");
+    assert_eq!(conn.read().await.unwrap(), b"hello");
+    conn.write(b"world").await.unwrap();
+    assert_eq!(client.join().unwrap(), b"world");
+    assert!(matches!(conn.read().await, Err(FrameError::Closed)));
+}
+
+/// Handshake of initiator id with key against acceptor knowing only client, returns both errors
+async fn rejected(id: &'static str, key: &'static [u8]) -> (String, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let res = ConnBuilder::new(addr).unwrap().init(id, key);
+        res.err().unwrap().to_string()
+    });
+
+    let (stream, _) = listener.accept().await.unwrap();
+    let res = AsyncConnBuilder::from(stream)
+        .accept(|id| (id == "client").then(|| b"key".to_vec()))
+        .await;
+    (client.join().unwrap(), res.err().unwrap().to_string())
+}
+
+#[tokio::test]
+async fn unknown_client_rejected_like_wrong_key() {
+    let (unknown, unknown_api) = rejected("other", b"key").await;
+    let (wrong, wrong_api) = rejected("client", b"wrong").await;
+    assert_eq!(unknown, wrong);
+    assert_eq!(unknown_api, "handshake rejected: unknown client other");
+    assert_eq!(
+        wrong_api,
+        "handshake failed: client client used an invalid key"
+    );
+}
+
+#[tokio::test]
+async fn split_halves_carry_typed_messages() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // blocking client answers each command with console output
+    let client = thread::spawn(move || {
+        let conn = ConnBuilder::new(addr)
+            .unwrap()
+            .init("client", b"key")
+            .unwrap();
+        let (mut reader, mut writer) = conn.split().unwrap();
+        writer.send(&console(1, "ready")).unwrap();
+        let Message::Command(cmd) = reader.recv().unwrap() else {
+            panic!("expected command");
+        };
+        writer.send(&console(2, &cmd)).unwrap();
+    });
+
+    // async acceptor
+    let (stream, _) = listener.accept().await.unwrap();
+    let conn = AsyncConnBuilder::from(stream)
+        .accept(|_| Some(b"key".to_vec()))
+        .await
+        .unwrap();
+    let (mut reader, mut writer) = conn.into_split();
+    assert_eq!(reader.recv().await.unwrap(), console(1, "ready"));
+    writer.send(&Message::Command("stop".into())).await.unwrap();
+    assert_eq!(reader.recv().await.unwrap(), console(2, "stop"));
+    client.join().unwrap();
+}
+
+#[tokio::test]
+async fn silent_peers_time_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let timeout = Some(Duration::from_millis(200));
+
+    // blocking client waits for a frame that never arrives
+    let client = thread::spawn(move || {
+        let mut conn = ConnBuilder::new(addr)
+            .unwrap()
+            .read_timeout(timeout)
+            .init("client", b"key")
+            .unwrap();
+        let res = conn.recv();
+        (matches!(res, Err(FrameError::TimedOut)), conn)
+    });
+
+    // async acceptor does the same
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut conn = AsyncConnBuilder::from(stream)
+        .read_timeout(timeout)
+        .accept(|id| (id == "client").then(|| b"key".to_vec()))
+        .await
+        .unwrap();
+    assert!(matches!(conn.recv().await, Err(FrameError::TimedOut)));
+    assert!(client.join().unwrap().0);
+}
@@ -0,0 +1,136 @@
+//! Framing and decoder tests
+#![cfg(all(target_os = "linux", feature = "blocking"))]
+
+use proptest::prelude::*;
+use std::io::Cursor;
+use std::net::TcpListener;
+use std::thread;
+use wu::crypto::{COUNTER_LEN, CryptError, Crypter, TAG_LEN};
+use wu::net::{ConnBuilder, FrameError, read_frame, write_frame};
+
+const MAX: usize = 4096;
+
+/// Crypter pair sharing keys in opposite directions
+fn crypters() -> (Crypter, Crypter) {
+    let (a, b) = ([1u8; 32], [2u8; 32]);
+    (Crypter::new(a, b).unwrap(), Crypter::new(b, a).unwrap())
+}
+
+/// Encode encrypted frame
+fn encode(crypter: &mut Crypter, data: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_frame(&mut buf, &crypter.encrypt(data).unwrap(), MAX).unwrap();
+    buf
+}
+
+proptest! {
+    #[test]
+    fn roundtrip(frames in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..512), 1..8)) {
+        let (mut tx, mut rx) = crypters();
+        let buf: Vec<u8> = frames.iter().flat_map(|f| encode(&mut tx, f)).collect();
+        let mut cursor = Cursor::new(buf);
+        for frame in &frames {
+            let enc = read_frame(&mut cursor, MAX).unwrap();
+            prop_assert_eq!(&rx.decrypt(enc).unwrap(), frame);
+        }
+        prop_assert!(matches!(read_frame(&mut cursor, MAX), Err(FrameError::Closed)));
+    }
+
+    #[test]
+    fn arbitrary_input_never_panics(input in prop::collection::vec(any::<u8>(), 0..2048)) {
+        let (_, mut rx) = crypters();
+        let mut cursor = Cursor::new(input);
+        while let Ok(enc) = read_frame(&mut cursor, MAX) {
+            prop_assert!(enc.len() <= MAX);
+            let _ = rx.decrypt(enc);
+        }
+    }
+
+    #[test]
+    fn oversized_length_rejected(len in (MAX as u64 + 1)..=u64::MAX) {
+        let mut cursor = Cursor::new(len.to_be_bytes().to_vec());
+        let res = read_frame(&mut cursor, MAX);
+        let oversized = matches!(res, Err(FrameError::Oversized { len: l, max: MAX }) if l == len);
+        prop_assert!(oversized);
+    }
+
+    #[test]
+    fn truncated_frame_detected(data in prop::collection::vec(any::<u8>(), 1..512), cut in any::<prop::sample::Index>()) {
+        let (mut tx, _) = crypters();
+        let buf = encode(&mut tx, &data);
+        let cut = 1 + cut.index(buf.len() - 1);
+        let res = read_frame(&mut Cursor::new(&buf[..cut]), MAX);
+        prop_assert!(matches!(res, Err(FrameError::Truncated)));
+    }
+
+    #[test]
+    fn tampered_frame_rejected(data in prop::collection::vec(any::<u8>(), 0..512), pos in any::<prop::sample::Index>(), bit in 0u8..8) {
+        let (mut tx, mut rx) = crypters();
+        let mut enc = tx.encrypt(&data).unwrap();
+        let pos = pos.index(enc.len());
+        enc[pos] ^= 1 << bit;
+        let res = rx.decrypt(enc);
+        let rejected = matches!(res, Err(CryptError::Authentication) | Err(CryptError::Replay { .. }));
+        prop_assert!(rejected);
+    }
+}
+
+#[test]
+fn replayed_frame_rejected() {
+    let (mut tx, mut rx) = crypters();
+    let first = tx.encrypt(b"first").unwrap();
+    let second = tx.encrypt(b"second").unwrap();
+    assert_eq!(rx.decrypt(&first).unwrap(), b"first");
+    assert_eq!(
+        rx.decrypt(&first),
+        Err(CryptError::Replay {
+            expected: 1,
+            got: 0
+        })
+    );
+    assert_eq!(rx.decrypt(&second).unwrap(), b"second");
+}
+
+#[test]
+fn reordered_frame_rejected() {
+    let (mut tx, mut rx) = crypters();
+    tx.encrypt(b"skipped").unwrap();
+    let second = tx.encrypt(b"second").unwrap();
+    assert_eq!(
+        rx.decrypt(second),
+        Err(CryptError::Replay {
+            expected: 0,
+            got: 1
+        })
+    );
+}
+
+#[test]
+fn oversized_write_rejected() {
+    let res = write_frame(&mut Vec::new(), &[0u8; MAX + 1], MAX);
+    assert!(matches!(res, Err(FrameError::Oversized { .. })));
+}
+
+#[test]
+fn connection_usable_after_oversized_write() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let peer = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut conn = ConnBuilder::from(stream)
+            .max_frame_len(MAX)
+            .accept(|_| Some(b"key".to_vec()))
+            .unwrap();
+        conn.read().unwrap()
+    });
+
+    // data filling a frame exactly is sent, one byte more is rejected before encrypting
+    let mut conn = ConnBuilder::new(addr)
+        .unwrap()
+        .max_frame_len(MAX)
+        .init("client", b"key")
+        .unwrap();
+    assert!(conn.write([0u8; MAX - COUNTER_LEN - TAG_LEN + 1]).is_err());
+    conn.write([1u8; MAX - COUNTER_LEN - TAG_LEN]).unwrap();
+    assert_eq!(peer.join().unwrap(), [1u8; MAX - COUNTER_LEN - TAG_LEN]);
+}
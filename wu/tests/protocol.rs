@@ -0,0 +1,114 @@
+//! Message schema tests
+#![cfg(target_os = "linux")]
+
+use wu::protocol::{
+    CgroupStats, DiskIo, Handler, HostStats, Message, MountStats, NetStats, PressureStats,
+    ProcessStats, ProtocolError, REVISION, VERSION,
+};
+
+#[test]
+fn messages_roundtrip() {
+    let messages = [
+        Message::Register {
+            handler: Handler::AddServer,
+            name: "Lobby".to_string(),
+            session: 7,
+        },
+        Message::Rejected {
+            reason: "taken".to_string(),
+        },
+        Message::Console {
+            seq: 1,
+            line: "line\n".to_string(),
+        },
+        Message::Ack(1),
+        Message::Stats(Box::new(HostStats {
+            cpu: 12.5,
+            mem: (1, 2),
+            disk: (3, 4),
+            load: (0.5, 0.25, 0.125),
+            cores: vec![10.0, 15.0],
+            swap: (5, 6),
+            uptime: 3600,
+            net: vec![NetStats {
+                interface: "eth0".to_string(),
+                rx: 100,
+                tx: 200,
+            }],
+            io: vec![DiskIo {
+                device: "sda".to_string(),
+                read: 300,
+                write: 400,
+            }],
+            mounts: vec![MountStats {
+                path: "/".to_string(),
+                used: 3,
+                total: 4,
+            }],
+            cgroup: Some(CgroupStats {
+                version: 2,
+                cpu_quota: Some(1.5),
+                mem_limit: None,
+                pids: Some((12, Some(100))),
+            }),
+            pressure: vec![PressureStats {
+                resource: "io".to_string(),
+                some: 1.5,
+                full: 0.5,
+            }],
+        })),
+        Message::Process(ProcessStats {
+            processes: 2,
+            cpu: 150.5,
+            cpu_time: 1200,
+            rss: 2048,
+            threads: 12,
+            fds: 40,
+            read: 500,
+            write: 600,
+        }),
+    ];
+    for msg in messages {
+        assert_eq!(Message::decode(&msg.encode().unwrap()).unwrap(), msg);
+    }
+}
+
+#[test]
+fn version_mismatch_detected() {
+    let mut buf = Message::Heartbeat.encode().unwrap();
+    buf[..2].copy_from_slice(&(VERSION + 1).to_be_bytes());
+    assert_eq!(
+        Message::decode(&buf),
+        Err(ProtocolError::Version {
+            local: VERSION,
+            remote: VERSION + 1
+        })
+    );
+}
+
+#[test]
+fn truncated_message_rejected() {
+    let buf = Message::Command("line".to_string()).encode().unwrap();
+    assert!(matches!(
+        Message::decode(&buf[..buf.len() - 1]),
+        Err(ProtocolError::Malformed(_))
+    ));
+}
+
+#[test]
+fn newer_revision_decoded() {
+    let mut buf = Message::Ack(7).encode().unwrap();
+    buf[2..4].copy_from_slice(&(REVISION + 1).to_be_bytes());
+    buf.extend_from_slice(&[1, 2, 3]);
+    assert_eq!(Message::decode(&buf), Ok(Message::Ack(7)));
+}
+
+#[test]
+fn incompatible_decoded_across_versions() {
+    let msg = Message::Incompatible {
+        version: VERSION + 1,
+    };
+    let buf = msg.encode().unwrap();
+    assert_eq!(buf, (VERSION + 1).to_be_bytes());
+    assert_eq!(Message::decode(&buf), Ok(msg));
+}